@@ -0,0 +1,59 @@
+//! Input handling and test data generation shared by the different days
+
+mod random;
+
+pub use random::Rng;
+
+/// Split a batch into blocks separated by blank lines, returning the lines of each block
+///
+/// Both `\n` and `\r\n` line endings are accepted and trailing whitespace is removed from
+/// every line. Lines with only whitespace count as blank, several blank lines in a row
+/// are the same as one and blank lines at the start or end of the batch are ignored.
+pub fn split_blocks(batch: &str) -> Vec<Vec<&str>> {
+    let mut blocks = Vec::new();
+    let mut current = Vec::new();
+    for line in batch.lines().map(str::trim_end) {
+        if line.trim_start().is_empty() {
+            if !current.is_empty() {
+                blocks.push(current);
+                current = Vec::new();
+            }
+        } else {
+            current.push(line);
+        }
+    }
+    if !current.is_empty() {
+        blocks.push(current);
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_split_blocks() {
+        let blocks = split_blocks("abc\n\na\nb\n\nc");
+        assert_eq!(blocks, vec![vec!["abc"], vec!["a", "b"], vec!["c"]]);
+    }
+
+    #[test]
+    fn test_split_blocks_crlf() {
+        let blocks = split_blocks("abc\r\n\r\na\r\nb\r\n");
+        assert_eq!(blocks, vec![vec!["abc"], vec!["a", "b"]]);
+    }
+
+    #[test]
+    fn test_split_blocks_blank_lines() {
+        let blocks = split_blocks("\n\nabc  \n \t\n\n\na\t\nb\n\n\n");
+        assert_eq!(blocks, vec![vec!["abc"], vec!["a", "b"]]);
+    }
+
+    #[test]
+    fn test_split_blocks_empty() {
+        assert!(split_blocks("").is_empty());
+        assert!(split_blocks("\n \n").is_empty());
+    }
+}
@@ -0,0 +1,51 @@
+/// A small seeded random number generator, to make up inputs for tests and benchmarks
+///
+/// It is a linear congruential generator, so the same seed always gives the same numbers,
+/// but it is not meant for anything that needs good randomness.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    /// A number from 0 up to, but not including, `m`, which must not be 0
+    ///
+    /// The whole state is scaled down to the range, so any `m` up to `usize::MAX` can be
+    /// reached, and the low bits, which repeat quickly, matter the least.
+    pub fn below(&mut self, m: usize) -> usize {
+        self.state = self.state.wrapping_mul(6364136223846793005).wrapping_add(1);
+        ((self.state as u128 * m as u128) >> 64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_below() {
+        let mut rng = Rng::new(42);
+        let numbers: Vec<usize> = (0..1000).map(|_| rng.below(10)).collect();
+        assert!(numbers.iter().all(|n| *n < 10));
+        assert!((0..10).all(|n| numbers.contains(&n)));
+
+        let mut again = Rng::new(42);
+        assert!(numbers.iter().all(|n| *n == again.below(10)));
+        let mut other = Rng::new(7);
+        assert!(numbers.iter().any(|n| *n != other.below(10)));
+    }
+
+    #[test]
+    fn test_below_large() {
+        let mut rng = Rng::new(42);
+        let numbers: Vec<usize> = (0..1000).map(|_| rng.below(usize::MAX)).collect();
+        assert!(numbers.iter().all(|n| *n < usize::MAX));
+        assert!(numbers.iter().any(|n| *n > u32::MAX as usize));
+        assert_eq!(rng.below(1), 0);
+    }
+}
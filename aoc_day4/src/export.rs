@@ -0,0 +1,232 @@
+use serde_json::{Map, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+use std::fmt;
+
+/// First columns of the exported CSV files, in order. Any other key goes after them.
+pub const COLUMNS: [&str; 8] = ["byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid", "cid"];
+
+/// Error produced when importing a batch of passports
+#[derive(Debug)]
+pub enum ImportError {
+    Csv(csv::Error),
+    Json(serde_json::Error),
+    /// A JSON line that is not an object, or has a non scalar value
+    Record {
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::Csv(e) => write!(f, "CSV error: {}", e),
+            ImportError::Json(e) => write!(f, "JSON error: {}", e),
+            ImportError::Record { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl Error for ImportError {}
+
+impl From<csv::Error> for ImportError {
+    fn from(e: csv::Error) -> Self {
+        ImportError::Csv(e)
+    }
+}
+
+impl From<serde_json::Error> for ImportError {
+    fn from(e: serde_json::Error) -> Self {
+        ImportError::Json(e)
+    }
+}
+
+/// Export the records as CSV, with a header and one row per passport
+///
+/// The columns are those in `COLUMNS` followed by any other key found in the records,
+/// sorted, so no key is lost. Missing fields are left as empty cells, the same as fields
+/// with an empty value, so those cannot be told apart when importing it back.
+pub fn to_csv(records: &[HashMap<String, String>]) -> String {
+    let extra: BTreeSet<&str> = records
+        .iter()
+        .flat_map(|record| record.keys())
+        .map(|k| k.as_str())
+        .filter(|k| !COLUMNS.contains(k))
+        .collect();
+    let columns: Vec<&str> = COLUMNS.iter().copied().chain(extra).collect();
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(&columns).unwrap();
+    for record in records.iter() {
+        let row = columns
+            .iter()
+            .map(|k| record.get(*k).map_or("", |v| v.as_str()));
+        writer.write_record(row).unwrap();
+    }
+    String::from_utf8(writer.into_inner().unwrap()).unwrap()
+}
+
+/// Import records from CSV
+///
+/// The header decides which field is in each column, so they can be in any order.
+/// Empty cells are treated as missing fields. A field exported with an empty value, like
+/// `byr:`, is therefore lost, so a passport with it may no longer have every required
+/// field. JSON Lines keeps it.
+pub fn from_csv(data: &str) -> Result<Vec<HashMap<String, String>>, ImportError> {
+    let mut reader = csv::Reader::from_reader(data.as_bytes());
+    let header = reader.headers()?.clone();
+
+    let mut records = Vec::new();
+    for row in reader.records() {
+        let record = header
+            .iter()
+            .zip(row?.iter())
+            .filter(|(_, v)| !v.is_empty())
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Export the records as JSON Lines, one object per passport
+///
+/// All keys are kept, including those not in `COLUMNS`.
+pub fn to_jsonl(records: &[HashMap<String, String>]) -> String {
+    let mut output = String::new();
+    for record in records.iter() {
+        let object: Map<String, Value> = record
+            .iter()
+            .map(|(k, v)| (k.clone(), Value::String(v.clone())))
+            .collect();
+        output.push_str(&Value::Object(object).to_string());
+        output.push('\n');
+    }
+    output
+}
+
+/// Import records from JSON Lines
+///
+/// Numbers are accepted and converted to strings, null values are treated as missing
+/// fields and blank lines are skipped.
+pub fn from_jsonl(data: &str) -> Result<Vec<HashMap<String, String>>, ImportError> {
+    let mut records = Vec::new();
+    for (i, line) in data.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let object = match serde_json::from_str(line)? {
+            Value::Object(object) => object,
+            _ => {
+                return Err(ImportError::Record {
+                    line: i + 1,
+                    message: "expected a JSON object".to_string(),
+                })
+            }
+        };
+
+        let mut record = HashMap::new();
+        for (k, v) in object.into_iter() {
+            let value = match v {
+                Value::String(s) => s,
+                Value::Number(n) => n.to_string(),
+                Value::Null => continue,
+                _ => {
+                    return Err(ImportError::Record {
+                        line: i + 1,
+                        message: format!("unsupported value for field '{}'", k),
+                    })
+                }
+            };
+            record.insert(k, value);
+        }
+        records.push(record);
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn records() -> Vec<HashMap<String, String>> {
+        [
+            [("byr", "1937"), ("hcl", "#fffffd"), ("cid", "147")],
+            [("pid", "028048884"), ("hgt", "59in"), ("ecl", "amb")],
+        ]
+        .iter()
+        .map(|r| {
+            r.iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        })
+        .collect()
+    }
+
+    #[test]
+    fn test_to_csv() {
+        let csv = to_csv(&records());
+        assert_eq!(
+            csv,
+            "byr,iyr,eyr,hgt,hcl,ecl,pid,cid
+1937,,,,#fffffd,,,147
+,,,59in,,amb,028048884,
+"
+        );
+    }
+
+    #[test]
+    fn test_csv_round_trip() {
+        assert_eq!(from_csv(&to_csv(&records())).unwrap(), records());
+    }
+
+    #[test]
+    fn test_csv_extra_keys() {
+        let mut records = records();
+        records[0].insert("zzz".to_string(), "1".to_string());
+        records[1].insert("abc".to_string(), "x,y".to_string());
+        let csv = to_csv(&records);
+        assert!(csv.starts_with("byr,iyr,eyr,hgt,hcl,ecl,pid,cid,abc,zzz\n"));
+        assert_eq!(from_csv(&csv).unwrap(), records);
+        assert_eq!(from_jsonl(&to_jsonl(&records)).unwrap(), records);
+    }
+
+    #[test]
+    fn test_csv_loses_empty_values() {
+        let mut records = records();
+        records[0].insert("iyr".to_string(), String::new());
+        let imported = from_csv(&to_csv(&records)).unwrap();
+        assert!(!imported[0].contains_key("iyr"));
+        assert_eq!(imported[0].len(), records[0].len() - 1);
+        assert_eq!(from_jsonl(&to_jsonl(&records)).unwrap(), records);
+    }
+
+    #[test]
+    fn test_from_csv_column_order() {
+        let records = from_csv("pid,byr\n\"000000001\",1980\n,1990\n").unwrap();
+        assert_eq!(records[0]["pid"], "000000001");
+        assert_eq!(records[0]["byr"], "1980");
+        assert!(!records[1].contains_key("pid"));
+    }
+
+    #[test]
+    fn test_jsonl_round_trip() {
+        let jsonl = to_jsonl(&records());
+        assert_eq!(jsonl.lines().count(), 2);
+        assert_eq!(from_jsonl(&jsonl).unwrap(), records());
+    }
+
+    #[test]
+    fn test_from_jsonl() {
+        let records = from_jsonl("{\"byr\": 1937, \"cid\": null}\n\n{\"pid\": \"1\"}").unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["byr"], "1937");
+        assert!(!records[0].contains_key("cid"));
+
+        assert!(from_jsonl("[1, 2]").is_err());
+        assert!(from_jsonl("{\"byr\": [1]}").is_err());
+        assert!(from_jsonl("{\"byr\":").is_err());
+    }
+}
@@ -1,117 +1,78 @@
-use regex::Regex;
+mod export;
+mod passport;
+
+use aoc_common::split_blocks;
+use export::{from_csv, from_jsonl, to_csv, to_jsonl};
+use passport::Passport;
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::env;
 use std::fs;
 
 /// Split batch file into individual records
+///
+/// Records are separated by one or more blank lines. Line endings can be CRLF and any
+/// trailing whitespace is ignored.
 fn split_batch(batch: &str) -> Vec<String> {
-    batch.split("\n\n").map(|s| s.to_string()).collect()
+    split_blocks(batch)
+        .iter()
+        .map(|lines| lines.join("\n"))
+        .collect()
 }
 
+/// Fields that must be present for a passport to be valid. `cid` is optional.
+const REQUIRED_FIELDS: [&str; 7] = ["byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid"];
+
 /// Split the records into the individual key:value pairs
 ///
-/// First, it splits each record into groups of key:value at each space and new line.
-/// Then, this is further split into the : and the result stored into a HashMap
+/// First, it splits each record into groups of key:value at any whitespace.
+/// Then, this is further split into the : and the result stored into a HashMap. Tokens
+/// without a colon are malformed and simply ignored.
 fn split_records(records: Vec<String>) -> Vec<HashMap<String, String>> {
     records
         .iter()
         .map(|s| {
-            s.split(|c| c == '\n' || c == ' ')
-                .map(|s| s.split(":"))
-                .map(|mut s| (s.next().unwrap().into(), s.next().unwrap().into()))
+            s.split_whitespace()
+                .filter_map(|s| s.split_once(':'))
+                .map(|(k, v)| (k.into(), v.into()))
                 .collect::<HashMap<String, String>>()
         })
         .collect()
 }
 
-/// Count the number of valid records based on the keys they have
-fn count_valid(records: &Vec<HashMap<String, String>>) -> usize {
-    records.iter().filter(|s| is_valid(&s)).count()
+/// Number of valid and trully valid passports in a batch
+#[derive(Debug, Default, PartialEq)]
+struct Summary {
+    valid: usize,
+    trully_valid: usize,
 }
 
-/// Count the number of trully valid records based on the keys they have
-fn count_trully_valid(records: &Vec<HashMap<String, String>>) -> usize {
-    records.iter().filter(|s| is_trully_valid(&s)).count()
+/// Validate the whole batch in a single pass
+///
+/// Records missing any of the required fields are not parsed any further.
+fn summarise(records: &[HashMap<String, String>]) -> Summary {
+    let mut summary = Summary::default();
+    for record in records.iter().filter(|s| is_valid(s)) {
+        summary.valid += 1;
+        if is_trully_valid(record) {
+            summary.trully_valid += 1;
+        }
+    }
+    summary
 }
 
 /// Cheks if a particular record is valid... in principle
+///
+/// All the required fields must be present. Any other key is ignored.
 fn is_valid(record: &HashMap<String, String>) -> bool {
-    record.len() == 8 || (record.len() == 7 && !record.contains_key("cid"))
+    REQUIRED_FIELDS.iter().all(|k| record.contains_key(*k))
 }
 
 /// Checks if a particular record is trully valid
+///
+/// That is, if it can be converted into a fully typed passport.
 fn is_trully_valid(record: &HashMap<String, String>) -> bool {
-    if is_valid(&record) {
-        return validate_byr(&record["byr"])
-            && validate_iyr(&record["iyr"])
-            && validate_eyr(&record["eyr"])
-            && validate_hgt(&record["hgt"])
-            && validate_ecl(&record["ecl"])
-            && validate_hcl(&record["hcl"])
-            && validate_pid(&record["pid"]);
-    }
-    return false;
-}
-
-fn validate_byr(byr: &str) -> bool {
-    let date = byr.parse().unwrap();
-    1920 <= date && date <= 2002
-}
-
-fn validate_iyr(iyr: &str) -> bool {
-    let date = iyr.parse().unwrap();
-    2010 <= date && date <= 2020
-}
-
-fn validate_eyr(eyr: &str) -> bool {
-    let date = eyr.parse().unwrap();
-    2020 <= date && date <= 2030
-}
-
-fn validate_hgt(hgt: &str) -> bool {
-    let cm = Regex::new(r"^(\d{3})cm$").unwrap();
-    let inches = Regex::new(r"^(\d{2})in$").unwrap();
-
-    if hgt.contains("cm") {
-        let value = cm.captures(hgt);
-        match value {
-            None => return false,
-            _ => {
-                let v = value.unwrap()[1].parse().unwrap();
-                return 150 <= v && v <= 193;
-            }
-        }
-    } else if hgt.contains("in") {
-        let value = inches.captures(hgt);
-        match value {
-            None => return false,
-            _ => {
-                let v = value.unwrap()[1].parse().unwrap();
-                return 59 <= v && v <= 76;
-            }
-        }
-    }
-    return false;
-}
-
-fn validate_hcl(hcl: &str) -> bool {
-    let re = Regex::new(r"^(#[0-9a-f]{6})$").unwrap();
-    match re.captures(hcl) {
-        None => return false,
-        _ => return true,
-    }
-}
-
-fn validate_ecl(ecl: &str) -> bool {
-    ["amb", "blu", "brn", "gry", "grn", "hzl", "oth"].contains(&ecl)
-}
-
-fn validate_pid(pid: &str) -> bool {
-    let re = Regex::new(r"^(\d{9})$").unwrap();
-    match re.captures(pid) {
-        None => return false,
-        _ => return true,
-    }
+    Passport::try_from(record).is_ok()
 }
 
 /// Read file into a string
@@ -125,23 +86,49 @@ fn read_file(args: &[String]) -> String {
         .to_string()
 }
 
+/// Load the passports, choosing the format based on the file extension
+///
+/// Files ending in `.csv` or `.jsonl` are imported, anything else is read as a batch file.
+fn load_passports(filename: &str, raw: &str) -> Vec<HashMap<String, String>> {
+    if filename.ends_with(".csv") {
+        from_csv(raw).expect("Something went wrong importing the CSV file")
+    } else if filename.ends_with(".jsonl") {
+        from_jsonl(raw).expect("Something went wrong importing the JSONL file")
+    } else {
+        split_records(split_batch(raw))
+    }
+}
+
+/// Export the passports if requested with `--csv <file>` and/or `--jsonl <file>`
+fn export_passports(options: &[String], passports: &[HashMap<String, String>]) {
+    for option in options.chunks(2) {
+        let (content, filename) = match option {
+            [flag, filename] if flag == "--csv" => (to_csv(passports), filename),
+            [flag, filename] if flag == "--jsonl" => (to_jsonl(passports), filename),
+            _ => panic!("Usage: aoc_day4 <file> [--csv <file>] [--jsonl <file>]"),
+        };
+        fs::write(filename, content).expect("Something went wrong writing the file");
+        println!("Exported passports to {}", filename);
+    }
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let passports: Vec<HashMap<String, String>> = split_records(split_batch(&read_file(&args)));
+    let passports = load_passports(&args[1], &read_file(&args));
 
-    // Number of valid passports
-    let valid = count_valid(&passports);
-    println!("Valid passports: {}", valid);
+    // Number of valid and trully valid passports
+    let summary = summarise(&passports);
+    println!("Valid passports: {}", summary.valid);
+    println!("Trully valid passports: {}", summary.trully_valid);
 
-    // Number of trully valid passports
-    let valid = count_trully_valid(&passports);
-    println!("Trully valid passports: {}", valid);
+    export_passports(&args[2..], &passports);
 }
 
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use aoc_common::Rng;
 
     const BATCH: &str = "ecl:gry pid:860033327 eyr:2020 hcl:#fffffd
 byr:1937 iyr:2017 cid:147 hgt:183cm
@@ -189,6 +176,16 @@ iyr:2010 hgt:158cm hcl:#b6652a ecl:blu byr:1944 eyr:2021 pid:093154719";
         assert_eq!(split_batch(BATCH).len(), 4);
     }
 
+    #[test]
+    fn test_split_batch_messy_input() {
+        let messy = format!("\n\n{}\n\n\n \n", BATCH.replace('\n', " \r\n"));
+        assert_eq!(split_batch(&messy).len(), 4);
+        assert_eq!(
+            split_records(split_batch(&messy)),
+            split_records(split_batch(BATCH))
+        );
+    }
+
     #[test]
     fn test_split_records() {
         let split = split_records(split_batch(BATCH));
@@ -202,65 +199,82 @@ iyr:2010 hgt:158cm hcl:#b6652a ecl:blu byr:1944 eyr:2021 pid:093154719";
     #[test]
     fn test_valid() {
         let split = split_records(split_batch(BATCH));
-        assert_eq!(count_valid(&split), 2);
+        assert_eq!(summarise(&split).valid, 2);
     }
 
     #[test]
     fn test_trully_valid() {
         let invalid = split_records(split_batch(INVALID));
         let valid = split_records(split_batch(VALID));
-        assert_eq!(count_trully_valid(&invalid), 0);
-        assert_eq!(count_trully_valid(&valid), 4);
-    }
-
-    #[test]
-    fn test_validate_byr() {
-        assert!(validate_byr("1930"));
-        assert!(!validate_byr("930"));
-    }
-
-    #[test]
-    fn test_validate_iyr() {
-        assert!(validate_iyr("2015"));
-        assert!(!validate_iyr("930"));
+        assert_eq!(summarise(&invalid).trully_valid, 0);
+        assert_eq!(summarise(&valid).trully_valid, 4);
     }
 
     #[test]
-    fn test_validate_eyr() {
-        assert!(validate_eyr("2025"));
-        assert!(!validate_eyr("2930"));
+    fn test_split_records_malformed_tokens() {
+        let split = split_records(split_batch("byr:1937 garbage  iyr:2017"));
+        assert_eq!(split[0].len(), 2);
+        assert_eq!(split[0]["byr"], "1937");
+        assert_eq!(split[0]["iyr"], "2017");
     }
 
     #[test]
-    fn test_validate_hgt() {
-        assert!(!validate_hgt("60"));
-        assert!(validate_hgt("160cm"));
-        assert!(!validate_hgt("60cm"));
-        assert!(validate_hgt("70in"));
-        assert!(!validate_hgt("200in"));
+    fn test_valid_requires_named_keys() {
+        let split = split_records(split_batch(
+            "aaa:1 bbb:2 ccc:3 ddd:4 eee:5 fff:6 ggg:7 hhh:8
+
+byr:1937 iyr:2017 eyr:2020 hgt:183cm hcl:#fffffd ecl:gry pid:860033327 foo:bar",
+        ));
+        assert!(!is_valid(&split[0]));
+        assert!(is_valid(&split[1]));
     }
 
     #[test]
-    fn test_validate_hcl() {
-        assert!(validate_hcl("#124af4"));
-        assert!(!validate_hcl("124af4"));
-        assert!(!validate_hcl("#124f4"));
-        assert!(!validate_hcl("#1m4f44"));
+    fn test_trully_valid_malformed_values() {
+        let split = split_records(split_batch(
+            "byr:abc iyr:2017 eyr:2020 hgt:183cm hcl:#fffffd ecl:gry pid:860033327",
+        ));
+        assert!(is_valid(&split[0]));
+        assert!(!is_trully_valid(&split[0]));
     }
 
-    #[test]
-    fn test_validate_ecl() {
-        assert!(validate_ecl("blu"));
-        assert!(!validate_ecl("foo"));
-        assert!(!validate_ecl("blufoo"));
+    /// Generate a batch of passports with all the fields, about a third of them trully valid
+    fn generate_batch(n: usize) -> String {
+        let mut rng = Rng::new(42);
+        let mut next = |m: usize| rng.below(m);
+        let mut records = Vec::with_capacity(n);
+        for _ in 0..n {
+            records.push(format!(
+                "byr:{} iyr:{} eyr:{}\nhgt:{}cm hcl:#{:06x} ecl:{} pid:{:09} cid:{}",
+                1900 + next(120),
+                2005 + next(20),
+                2020 + next(11),
+                150 + next(44),
+                next(0x1000000),
+                ["amb", "blu", "brn", "gry", "grn", "hzl", "oth", "xyz"][next(8)],
+                next(1_000_000_000),
+                next(1000)
+            ));
+        }
+        records.join("\n\n")
     }
 
+    /// Times splitting and validating a million passports
     #[test]
-    fn test_validate_pid() {
-        assert!(validate_pid("000123442"));
-        assert!(!validate_pid("00123442"));
-        assert!(!validate_pid("0001234424"));
-        assert!(!validate_pid("0001n3442"));
-        assert!(!validate_pid("000123442n"));
+    #[ignore]
+    fn bench_million_passports() {
+        let batch = generate_batch(1_000_000);
+
+        let start = std::time::Instant::now();
+        let passports = split_records(split_batch(&batch));
+        let parsed = start.elapsed();
+        let summary = summarise(&passports);
+        let validated = start.elapsed() - parsed;
+
+        assert_eq!(summary.valid, 1_000_000);
+        println!(
+            "Parsed in {:?}, validated in {:?}: {:?}",
+            parsed, validated, summary
+        );
     }
 }
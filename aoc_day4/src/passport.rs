@@ -0,0 +1,360 @@
+use regex::Regex;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+/// Compiles the regex the first time it is needed, reusing it afterwards
+fn regex<'a>(cell: &'a OnceLock<Regex>, pattern: &str) -> &'a Regex {
+    cell.get_or_init(|| Regex::new(pattern).unwrap())
+}
+
+/// Error produced when a single field value cannot be parsed
+#[derive(Debug, PartialEq)]
+pub struct ParseFieldError;
+
+impl fmt::Display for ParseFieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid field value")
+    }
+}
+
+/// Error produced when a record cannot be converted into a passport
+#[derive(Debug, PartialEq)]
+pub enum PassportError {
+    /// A required field is not in the record
+    Missing(&'static str),
+    /// A field is present, but its value is not valid
+    Invalid(&'static str, String),
+}
+
+impl fmt::Display for PassportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PassportError::Missing(key) => write!(f, "missing field '{}'", key),
+            PassportError::Invalid(key, value) => {
+                write!(f, "invalid value '{}' for field '{}'", value, key)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PassportError {}
+
+/// Parses a four digits year within the given range
+pub fn parse_year(year: &str, min: u16, max: u16) -> Result<u16, ParseFieldError> {
+    if year.len() != 4 || !year.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(ParseFieldError);
+    }
+    match year.parse() {
+        Ok(date) if (min..=max).contains(&date) => Ok(date),
+        _ => Err(ParseFieldError),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Height {
+    Cm(u16),
+    In(u16),
+}
+
+impl FromStr for Height {
+    type Err = ParseFieldError;
+
+    fn from_str(hgt: &str) -> Result<Self, Self::Err> {
+        static CM: OnceLock<Regex> = OnceLock::new();
+        static INCHES: OnceLock<Regex> = OnceLock::new();
+        let cm = regex(&CM, r"^(\d{3})cm$");
+        let inches = regex(&INCHES, r"^(\d{2})in$");
+
+        if let Some(value) = cm.captures(hgt) {
+            match value[1].parse() {
+                Ok(v) if (150..=193).contains(&v) => Ok(Height::Cm(v)),
+                _ => Err(ParseFieldError),
+            }
+        } else if let Some(value) = inches.captures(hgt) {
+            match value[1].parse() {
+                Ok(v) if (59..=76).contains(&v) => Ok(Height::In(v)),
+                _ => Err(ParseFieldError),
+            }
+        } else {
+            Err(ParseFieldError)
+        }
+    }
+}
+
+impl fmt::Display for Height {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Height::Cm(v) => write!(f, "{}cm", v),
+            Height::In(v) => write!(f, "{}in", v),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HairColor {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl FromStr for HairColor {
+    type Err = ParseFieldError;
+
+    fn from_str(hcl: &str) -> Result<Self, Self::Err> {
+        static RE: OnceLock<Regex> = OnceLock::new();
+        let re = regex(&RE, r"^#([0-9a-f]{2})([0-9a-f]{2})([0-9a-f]{2})$");
+        let caps = re.captures(hcl).ok_or(ParseFieldError)?;
+        let channel = |i| u8::from_str_radix(&caps[i], 16).map_err(|_| ParseFieldError);
+        Ok(HairColor {
+            r: channel(1)?,
+            g: channel(2)?,
+            b: channel(3)?,
+        })
+    }
+}
+
+impl fmt::Display for HairColor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EyeColor {
+    Amb,
+    Blu,
+    Brn,
+    Gry,
+    Grn,
+    Hzl,
+    Oth,
+}
+
+impl FromStr for EyeColor {
+    type Err = ParseFieldError;
+
+    fn from_str(ecl: &str) -> Result<Self, Self::Err> {
+        match ecl {
+            "amb" => Ok(EyeColor::Amb),
+            "blu" => Ok(EyeColor::Blu),
+            "brn" => Ok(EyeColor::Brn),
+            "gry" => Ok(EyeColor::Gry),
+            "grn" => Ok(EyeColor::Grn),
+            "hzl" => Ok(EyeColor::Hzl),
+            "oth" => Ok(EyeColor::Oth),
+            _ => Err(ParseFieldError),
+        }
+    }
+}
+
+impl fmt::Display for EyeColor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            EyeColor::Amb => "amb",
+            EyeColor::Blu => "blu",
+            EyeColor::Brn => "brn",
+            EyeColor::Gry => "gry",
+            EyeColor::Grn => "grn",
+            EyeColor::Hzl => "hzl",
+            EyeColor::Oth => "oth",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Nine digits passport id. Leading zeros are significant only when displayed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PassportId(pub u32);
+
+impl FromStr for PassportId {
+    type Err = ParseFieldError;
+
+    fn from_str(pid: &str) -> Result<Self, Self::Err> {
+        static RE: OnceLock<Regex> = OnceLock::new();
+        let re = regex(&RE, r"^(\d{9})$");
+        if !re.is_match(pid) {
+            return Err(ParseFieldError);
+        }
+        pid.parse().map(PassportId).map_err(|_| ParseFieldError)
+    }
+}
+
+impl fmt::Display for PassportId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:09}", self.0)
+    }
+}
+
+/// A passport whose fields have all been parsed and validated
+#[derive(Debug, Clone, PartialEq)]
+pub struct Passport {
+    pub birth_year: u16,
+    pub issue_year: u16,
+    pub expiration_year: u16,
+    pub height: Height,
+    pub hair_color: HairColor,
+    pub eye_color: EyeColor,
+    pub passport_id: PassportId,
+    pub country_id: Option<String>,
+}
+
+impl TryFrom<&HashMap<String, String>> for Passport {
+    type Error = PassportError;
+
+    fn try_from(record: &HashMap<String, String>) -> Result<Self, Self::Error> {
+        fn field<T>(
+            record: &HashMap<String, String>,
+            key: &'static str,
+            parse: impl Fn(&str) -> Result<T, ParseFieldError>,
+        ) -> Result<T, PassportError> {
+            let value = record.get(key).ok_or(PassportError::Missing(key))?;
+            parse(value).map_err(|_| PassportError::Invalid(key, value.clone()))
+        }
+
+        Ok(Passport {
+            birth_year: field(record, "byr", |v| parse_year(v, 1920, 2002))?,
+            issue_year: field(record, "iyr", |v| parse_year(v, 2010, 2020))?,
+            expiration_year: field(record, "eyr", |v| parse_year(v, 2020, 2030))?,
+            height: field(record, "hgt", str::parse)?,
+            hair_color: field(record, "hcl", str::parse)?,
+            eye_color: field(record, "ecl", str::parse)?,
+            passport_id: field(record, "pid", str::parse)?,
+            country_id: record.get("cid").cloned(),
+        })
+    }
+}
+
+/// Displays the passport back in the batch key:value format
+impl fmt::Display for Passport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "byr:{} iyr:{} eyr:{} hgt:{} hcl:{} ecl:{} pid:{}",
+            self.birth_year,
+            self.issue_year,
+            self.expiration_year,
+            self.height,
+            self.hair_color,
+            self.eye_color,
+            self.passport_id
+        )?;
+        if let Some(cid) = &self.country_id {
+            write!(f, " cid:{}", cid)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn record(raw: &str) -> HashMap<String, String> {
+        raw.split_whitespace()
+            .filter_map(|s| s.split_once(':'))
+            .map(|(k, v)| (k.into(), v.into()))
+            .collect()
+    }
+
+    #[test]
+    fn test_passport_try_from() {
+        let raw = record("pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980 hcl:#623a2f");
+        let passport = Passport::try_from(&raw).unwrap();
+        assert_eq!(passport.birth_year, 1980);
+        assert_eq!(passport.issue_year, 2012);
+        assert_eq!(passport.expiration_year, 2030);
+        assert_eq!(passport.height, Height::In(74));
+        assert_eq!(
+            passport.hair_color,
+            HairColor {
+                r: 0x62,
+                g: 0x3a,
+                b: 0x2f
+            }
+        );
+        assert_eq!(passport.eye_color, EyeColor::Grn);
+        assert_eq!(passport.passport_id, PassportId(87499704));
+        assert_eq!(passport.country_id, None);
+    }
+
+    #[test]
+    fn test_passport_errors() {
+        let raw = record("hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980 hcl:#623a2f");
+        assert_eq!(Passport::try_from(&raw), Err(PassportError::Missing("pid")));
+
+        let raw = record("pid:087499704 hgt:74 ecl:grn iyr:2012 eyr:2030 byr:1980 hcl:#623a2f");
+        assert_eq!(
+            Passport::try_from(&raw),
+            Err(PassportError::Invalid("hgt", "74".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_passport_display() {
+        let raw = "byr:1944 iyr:2010 eyr:2021 hgt:158cm hcl:#b6652a ecl:blu pid:093154719 cid:88";
+        let passport = Passport::try_from(&record(raw)).unwrap();
+        assert_eq!(passport.to_string(), raw);
+    }
+
+    #[test]
+    fn test_parse_byr() {
+        assert!(parse_year("1930", 1920, 2002).is_ok());
+        assert!(parse_year("930", 1920, 2002).is_err());
+        assert!(parse_year("abc", 1920, 2002).is_err());
+        assert!(parse_year("+1930", 1920, 2002).is_err());
+        assert!(parse_year("", 1920, 2002).is_err());
+    }
+
+    #[test]
+    fn test_parse_iyr() {
+        assert!(parse_year("2015", 2010, 2020).is_ok());
+        assert!(parse_year("930", 2010, 2020).is_err());
+        assert!(parse_year("20x5", 2010, 2020).is_err());
+    }
+
+    #[test]
+    fn test_parse_eyr() {
+        assert!(parse_year("2025", 2020, 2030).is_ok());
+        assert!(parse_year("2930", 2020, 2030).is_err());
+    }
+
+    #[test]
+    fn test_parse_height() {
+        assert!("60".parse::<Height>().is_err());
+        assert!("160cm".parse::<Height>().is_ok());
+        assert!("60cm".parse::<Height>().is_err());
+        assert!("70in".parse::<Height>().is_ok());
+        assert!("200in".parse::<Height>().is_err());
+        assert!("cmin".parse::<Height>().is_err());
+        assert!("abccm".parse::<Height>().is_err());
+        assert!("".parse::<Height>().is_err());
+    }
+
+    #[test]
+    fn test_parse_hair_color() {
+        assert!("#124af4".parse::<HairColor>().is_ok());
+        assert!("124af4".parse::<HairColor>().is_err());
+        assert!("#124f4".parse::<HairColor>().is_err());
+        assert!("#1m4f44".parse::<HairColor>().is_err());
+    }
+
+    #[test]
+    fn test_parse_eye_color() {
+        assert!("blu".parse::<EyeColor>().is_ok());
+        assert!("foo".parse::<EyeColor>().is_err());
+        assert!("blufoo".parse::<EyeColor>().is_err());
+    }
+
+    #[test]
+    fn test_parse_passport_id() {
+        assert!("000123442".parse::<PassportId>().is_ok());
+        assert!("00123442".parse::<PassportId>().is_err());
+        assert!("0001234424".parse::<PassportId>().is_err());
+        assert!("0001n3442".parse::<PassportId>().is_err());
+        assert!("000123442n".parse::<PassportId>().is_err());
+    }
+}
@@ -1,80 +1,324 @@
-use regex::Regex;
+mod seat_map;
+
+use seat_map::SeatMap;
 use std::env;
-use std::fs;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::str::FromStr;
 
-/// Read file into a string
-fn read_file(args: &[String]) -> String {
+/// Open the file for reading it one boarding pass at a time
+fn open_file(args: &[String]) -> BufReader<File> {
     let filename = &args[1];
     println!("Reading {}", &args[1]);
 
-    fs::read_to_string(filename)
-        .expect("Something went wrong reading the file")
-        .trim_end()
-        .to_string()
+    BufReader::new(File::open(filename).expect("Something went wrong reading the file"))
+}
+
+/// Describes the geometry of the plane and how boarding passes are written
+///
+/// Rows and columns are encoded in binary with the given number of bits, using the
+/// first letter of each pair for 0 and the second one for 1.
+#[derive(Debug, Clone, PartialEq)]
+struct PlaneLayout {
+    row_bits: usize,
+    col_bits: usize,
+    row_letters: [char; 2],
+    col_letters: [char; 2],
+}
+
+/// The plane of the puzzle: 128 rows and 8 columns, encoded with F/B and L/R
+impl Default for PlaneLayout {
+    fn default() -> Self {
+        PlaneLayout {
+            row_bits: 7,
+            col_bits: 3,
+            row_letters: ['F', 'B'],
+            col_letters: ['L', 'R'],
+        }
+    }
 }
 
-/// Transform the input sequence of letters into a string of 0s and 1s
-fn to_binary_str(raw: &str) -> Vec<String> {
-    let ones = Regex::new(r"[BR]").unwrap();
-    let zeros = Regex::new(r"[FL]").unwrap();
+impl PlaneLayout {
+    /// Length of a boarding pass in this plane
+    fn pass_len(&self) -> usize {
+        self.row_bits + self.col_bits
+    }
+
+    /// Checks the boarding pass has the expected length and only legal letters
+    fn validate(&self, pass: &str) -> Result<(), ParseSeatError> {
+        let found = pass.chars().count();
+        if found != self.pass_len() {
+            return Err(ParseSeatError::Length {
+                expected: self.pass_len(),
+                found,
+            });
+        }
+        for (position, letter) in pass.chars().enumerate() {
+            let legal = if position < self.row_bits {
+                &self.row_letters
+            } else {
+                &self.col_letters
+            };
+            if !legal.contains(&letter) {
+                return Err(ParseSeatError::Letter { position, letter });
+            }
+        }
+        Ok(())
+    }
+
+    /// Encodes the seat as a boarding pass, eg. `FBFBBFFRLR` in the default layout
+    ///
+    /// A plane with a single row or column has 0 bits for it, which take no letters.
+    fn encode(&self, seat: &Seat) -> String {
+        let bits = |value: usize, width: usize, letters: &[char; 2]| {
+            (0..width)
+                .rev()
+                .map(|bit| letters[(value >> bit) & 1])
+                .collect::<String>()
+        };
+        bits(seat.row, self.row_bits, &self.row_letters)
+            + &bits(seat.col, self.col_bits, &self.col_letters)
+    }
 
-    ones.replace_all(&zeros.replace_all(raw, "0"), "1")
-        .split("\n")
-        .map(|s| s.to_string())
-        .collect()
+    /// Decodes a boarding pass straight into its seat id
+    ///
+    /// Each byte is checked and shifted into the id, without any intermediate string.
+    /// Only layouts with ASCII letters and up to 16 letters per pass are supported.
+    fn decode_id(&self, pass: &[u8]) -> Result<u16, ParseSeatError> {
+        if pass.len() != self.pass_len() {
+            return Err(ParseSeatError::Length {
+                expected: self.pass_len(),
+                found: pass.len(),
+            });
+        }
+        let mut id = 0;
+        for (position, &letter) in pass.iter().enumerate() {
+            let legal = if position < self.row_bits {
+                &self.row_letters
+            } else {
+                &self.col_letters
+            };
+            let bit = match letter {
+                l if l == legal[0] as u8 => 0,
+                l if l == legal[1] as u8 => 1,
+                _ => {
+                    return Err(ParseSeatError::Letter {
+                        position,
+                        letter: letter as char,
+                    })
+                }
+            };
+            id = (id << 1) | bit;
+        }
+        Ok(id)
+    }
+
+    /// Decodes the boarding passes one line at a time as they are read
+    fn decode_stream<R: BufRead>(&self, reader: R) -> PassDecoder<'_, R> {
+        PassDecoder {
+            layout: self,
+            lines: reader.split(b'\n'),
+            line: 0,
+        }
+    }
 }
 
+/// Error produced while decoding a stream of boarding passes
 #[derive(Debug)]
+enum DecodeError {
+    Io(io::Error),
+    Pass { line: usize, error: ParseSeatError },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::Io(e) => write!(f, "{}", e),
+            DecodeError::Pass { line, error } => write!(f, "line {}: {}", line, error),
+        }
+    }
+}
+
+/// Iterator over the seat ids of a stream of boarding passes, one per line
+///
+/// Blank lines are skipped, but still counted in the line numbers of errors.
+struct PassDecoder<'a, R> {
+    layout: &'a PlaneLayout,
+    lines: io::Split<R>,
+    line: usize,
+}
+
+impl<R: BufRead> Iterator for PassDecoder<'_, R> {
+    type Item = Result<u16, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let bytes = match self.lines.next()? {
+                Ok(bytes) => bytes,
+                Err(e) => return Some(Err(DecodeError::Io(e))),
+            };
+            self.line += 1;
+            let pass = bytes.strip_suffix(b"\r").unwrap_or(&bytes);
+            if pass.is_empty() {
+                continue;
+            }
+            let line = self.line;
+            return Some(
+                self.layout
+                    .decode_id(pass)
+                    .map_err(|error| DecodeError::Pass { line, error }),
+            );
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 struct Seat {
     row: usize,
     col: usize,
 }
 
 impl Seat {
-    fn factory(binary_str: &str) -> Seat {
-        let row = &binary_str[..7];
-        let col = &binary_str[7..];
+    /// Recovers the seat from its id
+    fn from_id(id: usize, layout: &PlaneLayout) -> Seat {
         Seat {
-            row: usize::from_str_radix(row, 2).unwrap(),
-            col: usize::from_str_radix(col, 2).unwrap(),
+            row: id >> layout.col_bits,
+            col: id & ((1 << layout.col_bits) - 1),
         }
     }
 
-    fn id(&self) -> usize {
-        self.row * 8 + self.col
+    fn id(&self, layout: &PlaneLayout) -> usize {
+        (self.row << layout.col_bits) + self.col
     }
 }
 
-/// Finds your seat id
-///
-/// The condition is that it should not be in the id list but the +1 and -1 should.
-fn find_id(ids: &Vec<usize>) -> Option<usize> {
-    let maxi = *ids.iter().max().unwrap();
-    let mini = *ids.iter().min().unwrap();
+/// Error produced when a boarding pass does not match the plane layout
+#[derive(Debug, PartialEq)]
+enum ParseSeatError {
+    Length { expected: usize, found: usize },
+    Letter { position: usize, letter: char },
+}
+
+impl fmt::Display for ParseSeatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseSeatError::Length { expected, found } => write!(
+                f,
+                "expected a boarding pass of {} letters, found {}",
+                expected, found
+            ),
+            ParseSeatError::Letter { position, letter } => {
+                write!(f, "illegal letter '{}' at position {}", letter, position)
+            }
+        }
+    }
+}
+
+/// Parses a boarding pass of the default layout
+impl FromStr for Seat {
+    type Err = ParseSeatError;
 
-    for id in mini..maxi {
-        if !ids.contains(&id) && ids.contains(&(id - 1)) && ids.contains(&(id + 1)) {
-            return Some(id);
+    fn from_str(pass: &str) -> Result<Self, Self::Err> {
+        let layout = PlaneLayout::default();
+        layout.validate(pass)?;
+        let id = layout.decode_id(pass.as_bytes())?;
+        Ok(Seat::from_id(id as usize, &layout))
+    }
+}
+
+/// Encodes the seat back as a boarding pass of the default layout
+impl fmt::Display for Seat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", PlaneLayout::default().encode(self))
+    }
+}
+
+/// Read the plane layout from the command line, if given
+///
+/// The expected arguments are the row and column bits and the letters for each, eg.
+/// `7 3 FB LR` for the default plane.
+fn read_layout(args: &[String]) -> PlaneLayout {
+    let usage =
+        "Usage: aoc_day5 <file> [--map] [<row bits> <col bits> <row letters> <col letters>]";
+    match args {
+        [] => PlaneLayout::default(),
+        [row_bits, col_bits, row_letters, col_letters] => {
+            let letters = |s: &str| -> [char; 2] {
+                let chars: Vec<char> = s.chars().collect();
+                match chars[..] {
+                    [zero, one] if zero != one => [zero, one],
+                    _ => panic!("{}", usage),
+                }
+            };
+            let layout = PlaneLayout {
+                row_bits: row_bits.parse().expect(usage),
+                col_bits: col_bits.parse().expect(usage),
+                row_letters: letters(row_letters),
+                col_letters: letters(col_letters),
+            };
+            let mut letters = layout.row_letters.iter().chain(layout.col_letters.iter());
+            if layout.pass_len() > 16 || !letters.all(char::is_ascii) {
+                panic!("Only ASCII letters and up to 16 letters per pass are supported");
+            }
+            layout
         }
+        _ => panic!("{}", usage),
     }
-    return None;
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let binary_str = to_binary_str(&read_file(&args));
-    let seats: Vec<Seat> = binary_str.iter().map(|s| Seat::factory(s)).collect();
-    let ids = seats.iter().map(|s| s.id()).collect::<Vec<usize>>();
+    let show_map = args.iter().any(|a| a == "--map");
+    let options: Vec<String> = args[2..]
+        .iter()
+        .filter(|a| *a != "--map")
+        .cloned()
+        .collect();
+    let layout = read_layout(&options);
+
+    // Boarding passes not matching the layout are reported and skipped
+    let mut ids = Vec::new();
+    for result in layout.decode_stream(open_file(&args)) {
+        match result {
+            Ok(id) => ids.push(id as usize),
+            Err(DecodeError::Pass { line, error }) => {
+                println!("Skipping line {}: {}", line, error)
+            }
+            Err(e) => panic!("Something went wrong reading the file: {}", e),
+        }
+    }
+
+    // Decoded ids always fit in the layout they were decoded with
+    let map = SeatMap::new(&layout, &ids).expect("Seat id out of the plane");
 
     // Maximum id
-    let maxi = ids.iter().max();
-    println!("Maximum seat id is {:?}", maxi.unwrap());
+    match ids.iter().max() {
+        None => println!("No valid seats were found!!"),
+        Some(maxi) => println!("Maximum seat id is {}", maxi),
+    }
 
     // Your id is...
-    let your_id = find_id(&ids);
+    let your_id = map.find_id();
     match your_id {
         None => println!("Your id was not found!!"),
-        Some(id) => println!("Your id is {}", id),
+        Some(id) => println!(
+            "Your id is {} - {}",
+            id,
+            layout.encode(&Seat::from_id(id, &layout))
+        ),
+    }
+
+    // Audit of the whole flight
+    println!("Empty seats: {}", map.empty_seats().len());
+    for gap in map.gaps().iter() {
+        println!("Gap of {} seat(s) at ids {:?}", gap.len(), gap);
+    }
+    for id in map.duplicates().iter() {
+        println!("Duplicate boarding pass for seat id {}", id);
+    }
+    if show_map {
+        print!("{}", map.render());
     }
 }
 
@@ -82,6 +326,43 @@ fn main() {
 mod tests {
 
     use super::*;
+    use regex::Regex;
+
+    /// Transform the input sequence of letters into a string of 0s and 1s
+    ///
+    /// This and `Seat::factory` are the original decoder, only kept as a reference to check
+    /// and benchmark `PlaneLayout::decode_id` against.
+    ///
+    /// Letters are replaced regardless of their position, so a letter used for rows and for
+    /// columns must mean the same bit in both.
+    fn to_binary_str(raw: &str, layout: &PlaneLayout) -> Vec<String> {
+        let letters = |i: usize| {
+            let pattern = format!(
+                "[{}{}]",
+                regex::escape(&layout.row_letters[i].to_string()),
+                regex::escape(&layout.col_letters[i].to_string())
+            );
+            Regex::new(&pattern).unwrap()
+        };
+        let ones = letters(1);
+        let zeros = letters(0);
+
+        ones.replace_all(&zeros.replace_all(raw, "0"), "1")
+            .split('\n')
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    impl Seat {
+        fn factory(binary_str: &str, layout: &PlaneLayout) -> Seat {
+            let row = &binary_str[..layout.row_bits];
+            let col = &binary_str[layout.row_bits..];
+            Seat {
+                row: usize::from_str_radix(row, 2).unwrap(),
+                col: usize::from_str_radix(col, 2).unwrap(),
+            }
+        }
+    }
 
     const SEATS: &str = "FBFBBFFRLR
 BFFFBBFRRR
@@ -90,7 +371,7 @@ BBFFBBFRLL";
 
     #[test]
     fn test_to_binary_str() {
-        let binary_str = to_binary_str(SEATS);
+        let binary_str = to_binary_str(SEATS, &PlaneLayout::default());
         assert_eq!(binary_str[0], "0101100101");
         assert_eq!(binary_str[1], "1000110111");
         assert_eq!(binary_str[2], "0001110111");
@@ -99,11 +380,12 @@ BBFFBBFRLL";
 
     #[test]
     fn test_seat_factory() {
-        let binary_str = to_binary_str(SEATS);
+        let layout = PlaneLayout::default();
+        let binary_str = to_binary_str(SEATS, &layout);
         let expected = [(44, 5), (70, 7), (14, 7), (102, 4)];
 
         for (s, exp) in binary_str.iter().zip(expected.iter()) {
-            let seat = Seat::factory(s);
+            let seat = Seat::factory(s, &layout);
             assert_eq!(seat.row, exp.0);
             assert_eq!(seat.col, exp.1);
         }
@@ -111,19 +393,237 @@ BBFFBBFRLL";
 
     #[test]
     fn test_seat_id() {
-        let binary_str = to_binary_str(SEATS);
+        let layout = PlaneLayout::default();
+        let binary_str = to_binary_str(SEATS, &layout);
         let expected = [357, 567, 119, 820];
 
         for (s, exp) in binary_str.iter().zip(expected.iter()) {
-            let seat = Seat::factory(s);
-            assert_eq!(seat.id(), *exp);
+            let seat = Seat::factory(s, &layout);
+            assert_eq!(seat.id(&layout), *exp);
         }
     }
 
     #[test]
     fn test_largest_id() {
-        let binary_str = to_binary_str(SEATS);
-        let seats: Vec<usize> = binary_str.iter().map(|s| Seat::factory(s).id()).collect();
+        let layout = PlaneLayout::default();
+        let binary_str = to_binary_str(SEATS, &layout);
+        let seats: Vec<usize> = binary_str
+            .iter()
+            .map(|s| Seat::factory(s, &layout).id(&layout))
+            .collect();
         assert_eq!(seats.iter().max(), Some(&820))
     }
+
+    #[test]
+    fn test_seat_from_str() {
+        let expected = [(44, 5), (70, 7), (14, 7), (102, 4)];
+
+        for (s, exp) in SEATS.split('\n').zip(expected.iter()) {
+            let seat: Seat = s.parse().unwrap();
+            assert_eq!((seat.row, seat.col), *exp);
+        }
+        assert!("FBFBBFFRL".parse::<Seat>().is_err());
+        assert!("FBFBBFFRLRL".parse::<Seat>().is_err());
+        assert!("FBFBBFRRLR".parse::<Seat>().is_err());
+        assert!("fbfbbffrlr".parse::<Seat>().is_err());
+    }
+
+    #[test]
+    fn test_seat_display() {
+        for s in SEATS.split('\n') {
+            assert_eq!(s.parse::<Seat>().unwrap().to_string(), s);
+        }
+        assert_eq!(
+            Seat::from_id(357, &PlaneLayout::default()).to_string(),
+            "FBFBBFFRLR"
+        );
+    }
+
+    #[test]
+    fn test_encode_decode_identity() {
+        let layout = PlaneLayout::default();
+        for id in 0..1024 {
+            let seat = Seat::from_id(id, &layout);
+            let pass = seat.to_string();
+            assert_eq!(pass.len(), 10);
+            assert_eq!(pass.parse::<Seat>(), Ok(seat));
+            assert_eq!(
+                Seat::factory(&to_binary_str(&pass, &layout)[0], &layout).id(&layout),
+                id
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let layout = PlaneLayout::default();
+        assert_eq!(layout.validate("FBFBBFFRLR"), Ok(()));
+        assert_eq!(
+            layout.validate("FBFBBFFRL"),
+            Err(ParseSeatError::Length {
+                expected: 10,
+                found: 9
+            })
+        );
+        assert_eq!(
+            layout.validate("FBFBBFRRLR"),
+            Err(ParseSeatError::Letter {
+                position: 6,
+                letter: 'R'
+            })
+        );
+        assert_eq!(
+            layout.validate("FBFBBFFRLB"),
+            Err(ParseSeatError::Letter {
+                position: 9,
+                letter: 'B'
+            })
+        );
+    }
+
+    #[test]
+    fn test_other_layout() {
+        // 512 rows and 16 columns, with up/down and west/east letters
+        let layout = PlaneLayout {
+            row_bits: 9,
+            col_bits: 4,
+            row_letters: ['U', 'D'],
+            col_letters: ['W', 'E'],
+        };
+        let pass = "UUUUUUUDUEWWE";
+        assert_eq!(layout.validate(pass), Ok(()));
+        assert!(layout.validate("FBFBBFFRLR").is_err());
+
+        let seat = Seat::factory(&to_binary_str(pass, &layout)[0], &layout);
+        assert_eq!((seat.row, seat.col), (2, 9));
+        assert_eq!(seat.id(&layout), 2 * 16 + 9);
+        assert_eq!(layout.encode(&seat), pass);
+
+        for id in 0..(1 << 13) {
+            let seat = Seat::from_id(id, &layout);
+            let pass = layout.encode(&seat);
+            assert_eq!(layout.validate(&pass), Ok(()));
+            assert_eq!(
+                Seat::factory(&to_binary_str(&pass, &layout)[0], &layout),
+                seat
+            );
+        }
+    }
+
+    #[test]
+    fn test_single_row_layout() {
+        // A single row of 8 seats, so the row takes no letters
+        let layout = PlaneLayout {
+            row_bits: 0,
+            col_bits: 3,
+            row_letters: ['F', 'B'],
+            col_letters: ['L', 'R'],
+        };
+        for id in 0..8 {
+            let seat = Seat::from_id(id, &layout);
+            let pass = layout.encode(&seat);
+            assert_eq!(pass.len(), 3);
+            assert_eq!(layout.decode_id(pass.as_bytes()), Ok(id as u16));
+        }
+        assert_eq!(layout.encode(&Seat::from_id(5, &layout)), "RLR");
+    }
+
+    #[test]
+    fn test_decode_id() {
+        let layout = PlaneLayout::default();
+        let expected = [357, 567, 119, 820];
+
+        for (s, exp) in SEATS.split('\n').zip(expected.iter()) {
+            assert_eq!(layout.decode_id(s.as_bytes()), Ok(*exp));
+        }
+        assert_eq!(
+            layout.decode_id(b"FBFBBFFRL"),
+            Err(ParseSeatError::Length {
+                expected: 10,
+                found: 9
+            })
+        );
+        assert_eq!(
+            layout.decode_id(b"FBFBBFRRLR"),
+            Err(ParseSeatError::Letter {
+                position: 6,
+                letter: 'R'
+            })
+        );
+    }
+
+    #[test]
+    fn test_decode_id_matches_reference() {
+        let layout = PlaneLayout::default();
+        for id in 0..1024 {
+            let pass = layout.encode(&Seat::from_id(id, &layout));
+            let reference = Seat::factory(&to_binary_str(&pass, &layout)[0], &layout);
+            assert_eq!(
+                layout.decode_id(pass.as_bytes()),
+                Ok(reference.id(&layout) as u16)
+            );
+        }
+    }
+
+    #[test]
+    fn test_decode_stream() {
+        let layout = PlaneLayout::default();
+        let input = "FBFBBFFRLR\r\nBFFFBBFRRR\n\nFBF\n\r\nBBFFBBFRLL\n\n";
+        let results: Vec<Result<u16, DecodeError>> =
+            layout.decode_stream(input.as_bytes()).collect();
+
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].as_ref().unwrap(), &357);
+        assert_eq!(results[1].as_ref().unwrap(), &567);
+        assert!(matches!(
+            results[2],
+            Err(DecodeError::Pass {
+                line: 4,
+                error: ParseSeatError::Length { .. }
+            })
+        ));
+        assert_eq!(results[3].as_ref().unwrap(), &820);
+    }
+
+    /// Decodes a million passes with the regex, byte-wise and streaming decoders
+    #[test]
+    #[ignore]
+    fn bench_decoders() {
+        let layout = PlaneLayout::default();
+        let passes: Vec<String> = (0..1_000_000)
+            .map(|i| layout.encode(&Seat::from_id((i * 7919) % 1024, &layout)))
+            .collect();
+        let raw = passes.join("\n");
+
+        let start = std::time::Instant::now();
+        let reference: Vec<usize> = to_binary_str(&raw, &layout)
+            .iter()
+            .map(|s| Seat::factory(s, &layout).id(&layout))
+            .collect();
+        let regex = start.elapsed();
+
+        let start = std::time::Instant::now();
+        let direct: Vec<u16> = raw
+            .split('\n')
+            .map(|s| layout.decode_id(s.as_bytes()).unwrap())
+            .collect();
+        let bytes = start.elapsed();
+
+        let start = std::time::Instant::now();
+        let streamed: Vec<u16> = layout
+            .decode_stream(raw.as_bytes())
+            .map(|r| r.unwrap())
+            .collect();
+        let stream = start.elapsed();
+
+        assert!(reference
+            .iter()
+            .zip(direct.iter())
+            .all(|(a, b)| *a == *b as usize));
+        assert_eq!(direct, streamed);
+        println!(
+            "Regex: {:?}, byte-wise: {:?}, streaming: {:?}",
+            regex, bytes, stream
+        );
+    }
 }
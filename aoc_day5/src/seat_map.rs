@@ -0,0 +1,200 @@
+use crate::{PlaneLayout, Seat};
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+/// Error produced when a seat id does not fit in the plane
+#[derive(Debug, PartialEq)]
+pub struct SeatIdError {
+    pub id: usize,
+    pub capacity: usize,
+}
+
+impl fmt::Display for SeatIdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "seat id {} is out of a plane of {} seats",
+            self.id, self.capacity
+        )
+    }
+}
+
+impl Error for SeatIdError {}
+
+/// Occupancy of every seat in the plane, stored as a bitset indexed by seat id
+#[derive(Debug)]
+pub struct SeatMap {
+    layout: PlaneLayout,
+    occupied: Vec<u64>,
+    duplicates: Vec<usize>,
+}
+
+impl SeatMap {
+    /// Builds the map from the ids of the boarding passes
+    ///
+    /// Ids found more than once are recorded as duplicates, rather than ignored. Fails on
+    /// the first id that is not a seat of the layout.
+    pub fn new(layout: &PlaneLayout, ids: &[usize]) -> Result<SeatMap, SeatIdError> {
+        let capacity: usize = 1 << layout.pass_len();
+        let mut map = SeatMap {
+            layout: layout.clone(),
+            occupied: vec![0; capacity.div_ceil(64)],
+            duplicates: Vec::new(),
+        };
+        for &id in ids.iter() {
+            if id >= capacity {
+                return Err(SeatIdError { id, capacity });
+            }
+            if map.is_occupied(id) {
+                map.duplicates.push(id);
+            }
+            map.occupied[id / 64] |= 1 << (id % 64);
+        }
+        map.duplicates.sort_unstable();
+        map.duplicates.dedup();
+        Ok(map)
+    }
+
+    /// Total number of seats in the plane
+    pub fn capacity(&self) -> usize {
+        1 << self.layout.pass_len()
+    }
+
+    pub fn is_occupied(&self, id: usize) -> bool {
+        self.occupied[id / 64] & (1 << (id % 64)) != 0
+    }
+
+    /// Ids of all seats nobody has a boarding pass for
+    pub fn empty_seats(&self) -> Vec<usize> {
+        (0..self.capacity())
+            .filter(|&id| !self.is_occupied(id))
+            .collect()
+    }
+
+    /// Ids found in more than one boarding pass, each listed once
+    pub fn duplicates(&self) -> &[usize] {
+        &self.duplicates
+    }
+
+    /// Runs of empty seats with occupied seats at both ends, of any length
+    ///
+    /// Empty seats at the very front or back of the plane are not gaps, as those seats
+    /// might just not exist.
+    pub fn gaps(&self) -> Vec<Range<usize>> {
+        let mut gaps = Vec::new();
+        let mut start = None;
+        let mut seen_occupied = false;
+        for id in 0..self.capacity() {
+            match (self.is_occupied(id), start) {
+                (true, Some(s)) => {
+                    gaps.push(s..id);
+                    start = None;
+                }
+                (true, None) => seen_occupied = true,
+                (false, None) if seen_occupied => start = Some(id),
+                _ => {}
+            }
+        }
+        gaps
+    }
+
+    /// Finds your seat id
+    ///
+    /// That is the only empty seat whose neighbours, id -1 and +1, are both occupied.
+    pub fn find_id(&self) -> Option<usize> {
+        self.gaps()
+            .into_iter()
+            .find(|gap| gap.len() == 1)
+            .map(|gap| gap.start)
+    }
+
+    /// Draws the cabin as a grid, one line per row, with `#` for occupied seats
+    pub fn render(&self) -> String {
+        let cols = 1 << self.layout.col_bits;
+        let rows = 1 << self.layout.row_bits;
+        let width = (rows - 1usize).to_string().len();
+
+        let mut output = String::new();
+        for row in 0..rows {
+            output.push_str(&format!("{:>width$} ", row, width = width));
+            for col in 0..cols {
+                let id = Seat { row, col }.id(&self.layout);
+                output.push(if self.is_occupied(id) { '#' } else { '.' });
+            }
+            output.push('\n');
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn small_layout() -> PlaneLayout {
+        PlaneLayout {
+            row_bits: 2,
+            col_bits: 2,
+            ..PlaneLayout::default()
+        }
+    }
+
+    #[test]
+    fn test_empty_seats_and_duplicates() {
+        let map = SeatMap::new(&small_layout(), &[1, 2, 2, 5, 9, 9, 9, 10]).unwrap();
+        assert_eq!(map.capacity(), 16);
+        assert!(map.is_occupied(5));
+        assert!(!map.is_occupied(6));
+        assert_eq!(
+            map.empty_seats(),
+            vec![0, 3, 4, 6, 7, 8, 11, 12, 13, 14, 15]
+        );
+        assert_eq!(map.duplicates(), &[2, 9]);
+    }
+
+    #[test]
+    fn test_gaps() {
+        let map = SeatMap::new(&small_layout(), &[1, 2, 5, 9, 10]).unwrap();
+        assert_eq!(map.gaps(), vec![3..5, 6..9]);
+        assert_eq!(map.find_id(), None);
+
+        let map = SeatMap::new(&small_layout(), &[1, 2, 4, 5, 9, 10]).unwrap();
+        assert_eq!(map.gaps(), vec![3..4, 6..9]);
+        assert_eq!(map.find_id(), Some(3));
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        let error = SeatMap::new(&small_layout(), &[1, 15, 16, 2]).unwrap_err();
+        assert_eq!(
+            error,
+            SeatIdError {
+                id: 16,
+                capacity: 16
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "seat id 16 is out of a plane of 16 seats"
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let map = SeatMap::new(&small_layout(), &[1, 2, 5, 9, 10]).unwrap();
+        assert_eq!(map.render(), "0 .##.\n1 .#..\n2 .##.\n3 ....\n");
+    }
+
+    #[test]
+    fn test_default_layout() {
+        let layout = PlaneLayout::default();
+        let ids: Vec<usize> = (100..900).filter(|&id| id != 562).collect();
+        let map = SeatMap::new(&layout, &ids).unwrap();
+        assert_eq!(map.capacity(), 1024);
+        assert_eq!(map.find_id(), Some(562));
+        assert_eq!(map.empty_seats().len(), 1024 - 799);
+        assert_eq!(map.render().lines().count(), 128);
+    }
+}
@@ -0,0 +1,275 @@
+use std::fmt;
+use std::ops::{BitAnd, BitOr, BitXor, Sub};
+
+/// Maximum number of questions an alphabet can have, one bit each in `Answers`
+pub const MAX_QUESTIONS: usize = 128;
+
+/// The letters identifying each question, in order
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alphabet {
+    letters: Vec<char>,
+}
+
+/// The questions of the puzzle, `a` to `z`
+impl Default for Alphabet {
+    fn default() -> Self {
+        Alphabet {
+            letters: ('a'..='z').collect(),
+        }
+    }
+}
+
+impl Alphabet {
+    /// Creates an alphabet with the given letters, which must be unique
+    pub fn new(letters: &str) -> Result<Alphabet, AnswerError> {
+        let mut unique: Vec<char> = Vec::new();
+        for c in letters.chars() {
+            if unique.contains(&c) {
+                return Err(AnswerError::Duplicate(c));
+            }
+            unique.push(c);
+        }
+        if unique.len() > MAX_QUESTIONS {
+            return Err(AnswerError::TooManyQuestions(unique.len()));
+        }
+        Ok(Alphabet { letters: unique })
+    }
+
+    /// Position of the letter in the alphabet, if it is there
+    pub fn index(&self, letter: char) -> Option<usize> {
+        self.letters.iter().position(|&c| c == letter)
+    }
+
+    /// Number of questions in the alphabet
+    pub fn len(&self) -> usize {
+        self.letters.len()
+    }
+
+    pub fn letters(&self) -> &[char] {
+        &self.letters
+    }
+}
+
+/// Error produced when the answers or the alphabet are not valid
+#[derive(Debug, PartialEq)]
+pub enum AnswerError {
+    /// The letter is not a question of the alphabet
+    Unknown(char),
+    /// The letter is more than once in the alphabet
+    Duplicate(char),
+    /// The alphabet has more letters than supported
+    TooManyQuestions(usize),
+}
+
+impl fmt::Display for AnswerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AnswerError::Unknown(c) => write!(f, "unknown answer {:?}", c),
+            AnswerError::Duplicate(c) => write!(f, "letter {:?} repeated in the alphabet", c),
+            AnswerError::TooManyQuestions(n) => write!(
+                f,
+                "alphabet with {} letters, but at most {} are supported",
+                n, MAX_QUESTIONS
+            ),
+        }
+    }
+}
+
+/// Set of questions answered YES, one bit per letter of the alphabet
+///
+/// The usual set operations are available as operators: `|` for the union, `&` for the
+/// intersection, `-` for the difference and `^` for the symmetric difference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Answers(u128);
+
+impl Answers {
+    /// Parses the answers of a single passenger
+    pub fn parse(answers: &str, alphabet: &Alphabet) -> Result<Answers, AnswerError> {
+        let mut set = Answers::default();
+        for c in answers.chars() {
+            let i = alphabet.index(c).ok_or(AnswerError::Unknown(c))?;
+            set.0 |= 1 << i;
+        }
+        Ok(set)
+    }
+
+    /// Number of questions in the set
+    pub fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    /// Checks if the question with the given index is in the set
+    pub fn contains(&self, question: usize) -> bool {
+        question < MAX_QUESTIONS && self.0 & (1 << question) != 0
+    }
+}
+
+impl BitOr for Answers {
+    type Output = Answers;
+
+    fn bitor(self, other: Answers) -> Answers {
+        Answers(self.0 | other.0)
+    }
+}
+
+impl BitAnd for Answers {
+    type Output = Answers;
+
+    fn bitand(self, other: Answers) -> Answers {
+        Answers(self.0 & other.0)
+    }
+}
+
+impl Sub for Answers {
+    type Output = Answers;
+
+    fn sub(self, other: Answers) -> Answers {
+        Answers(self.0 & !other.0)
+    }
+}
+
+impl BitXor for Answers {
+    type Output = Answers;
+
+    fn bitxor(self, other: Answers) -> Answers {
+        Answers(self.0 ^ other.0)
+    }
+}
+
+/// The answers of each passenger in a group
+#[derive(Debug, Clone, PartialEq)]
+pub struct Group {
+    members: Vec<Answers>,
+    questions: usize,
+}
+
+impl Group {
+    /// Parses a group from the answers of its passengers, one per item
+    pub fn parse<S: AsRef<str>>(
+        passengers: &[S],
+        alphabet: &Alphabet,
+    ) -> Result<Group, AnswerError> {
+        let members = passengers
+            .iter()
+            .map(|p| Answers::parse(p.as_ref(), alphabet))
+            .collect::<Result<_, _>>()?;
+        Ok(Group {
+            members,
+            questions: alphabet.len(),
+        })
+    }
+
+    /// Number of passengers in the group
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Questions answered YES by anyone in the group
+    pub fn union(&self) -> Answers {
+        self.members
+            .iter()
+            .fold(Answers::default(), |acc, &a| acc | a)
+    }
+
+    /// Questions answered YES by everyone in the group
+    pub fn intersection(&self) -> Answers {
+        match self.members.split_first() {
+            None => Answers::default(),
+            Some((first, rest)) => rest.iter().fold(*first, |acc, &a| acc & a),
+        }
+    }
+
+    /// Number of passengers of the group that answered YES to the question
+    pub fn count(&self, question: usize) -> usize {
+        self.members.iter().filter(|a| a.contains(question)).count()
+    }
+
+    /// Questions answered YES by at least `k` passengers of the group
+    pub fn at_least(&self, k: usize) -> Answers {
+        let mut set = Answers::default();
+        for question in 0..self.questions {
+            if self.count(question) >= k {
+                set.0 |= 1 << question;
+            }
+        }
+        set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn answers(s: &str) -> Answers {
+        Answers::parse(s, &Alphabet::default()).unwrap()
+    }
+
+    #[test]
+    fn test_alphabet() {
+        let alphabet = Alphabet::new("xyz").unwrap();
+        assert_eq!(alphabet.index('y'), Some(1));
+        assert_eq!(alphabet.index('a'), None);
+        assert_eq!(Alphabet::new("xyx"), Err(AnswerError::Duplicate('x')));
+
+        let wide: String = (0..200u32)
+            .filter_map(|i| char::from_u32(0x100 + i))
+            .collect();
+        assert_eq!(
+            Alphabet::new(&wide),
+            Err(AnswerError::TooManyQuestions(200))
+        );
+        assert!(Alphabet::new(&wide[..2 * MAX_QUESTIONS]).is_ok());
+    }
+
+    #[test]
+    fn test_answers_parse() {
+        let set = answers("cab");
+        assert_eq!(set.len(), 3);
+        assert!(set.contains(0) && set.contains(1) && set.contains(2));
+        assert!(!set.contains(3));
+        assert_eq!(answers("").len(), 0);
+        assert_eq!(
+            Answers::parse("aB", &Alphabet::default()),
+            Err(AnswerError::Unknown('B'))
+        );
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let (a, b) = (answers("abc"), answers("bcd"));
+        assert_eq!(a | b, answers("abcd"));
+        assert_eq!(a & b, answers("bc"));
+        assert_eq!(a - b, answers("a"));
+        assert_eq!(a ^ b, answers("ad"));
+    }
+
+    #[test]
+    fn test_group() {
+        let group = Group::parse(&["ab", "ac", "a"], &Alphabet::default()).unwrap();
+        assert_eq!(group.len(), 3);
+        assert_eq!(group.count(0), 3);
+        assert_eq!(group.count(1), 1);
+        assert_eq!(group.count(25), 0);
+        assert_eq!(group.union(), answers("abc"));
+        assert_eq!(group.intersection(), answers("a"));
+        assert_eq!(group.at_least(1), group.union());
+        assert_eq!(group.at_least(2), answers("a"));
+        assert_eq!(group.at_least(3), group.intersection());
+        assert_eq!(group.at_least(4).len(), 0);
+        assert_eq!(group.at_least(0).len(), 26);
+
+        let empty = Group::parse::<&str>(&[], &Alphabet::default()).unwrap();
+        assert_eq!(empty.union().len(), 0);
+        assert_eq!(empty.intersection().len(), 0);
+    }
+
+    #[test]
+    fn test_custom_alphabet() {
+        let alphabet = Alphabet::new("0123456789").unwrap();
+        let group = Group::parse(&["123", "345"], &alphabet).unwrap();
+        assert_eq!(group.union().len(), 5);
+        assert_eq!(group.intersection().len(), 1);
+        assert!(group.intersection().contains(3));
+    }
+}
@@ -1,3 +1,9 @@
+mod group;
+mod report;
+
+use aoc_common::split_blocks;
+use group::{Alphabet, AnswerError, Group};
+use report::Report;
 use std::env;
 use std::fs;
 
@@ -13,52 +19,109 @@ fn read_file(args: &[String]) -> String {
 }
 
 /// Split input file into individual groups
+///
+/// Groups are separated by one or more blank lines. Line endings can be CRLF and any
+/// trailing whitespace is ignored.
 fn split_groups(batch: &str) -> Vec<String> {
-    batch.split("\n\n").map(|s| s.to_string()).collect()
+    split_blocks(batch)
+        .iter()
+        .map(|lines| lines.join("\n"))
+        .collect()
 }
 
 /// Split grpups into individual passengers
-fn split_passengers(groups: &Vec<String>) -> Vec<Vec<String>> {
+fn split_passengers(groups: &[String]) -> Vec<Vec<String>> {
     groups
         .iter()
-        .map(|s| s.split("\n").map(|s| s.to_string()).collect())
+        .map(|s| s.split('\n').map(|s| s.to_string()).collect())
         .collect()
 }
 
-/// Count number of unique YES answers in group
-fn count_unique_in_group(groups: &Vec<Vec<String>>) -> Vec<usize> {
-    let mut yes_answers = Vec::new();
-
-    for g in groups.iter() {
-        let mut pass_answers: Vec<char> = g.join("").chars().collect();
-        pass_answers.sort_by(|a, b| a.cmp(b));
-        pass_answers.dedup();
-        yes_answers.push(pass_answers.len())
+/// Parse the answers of the passengers of each group
+///
+/// Groups with any answer not in the alphabet, like digits or spaces, are rejected. They
+/// are returned apart, with their position in the batch, so they can be reported.
+fn parse_groups(
+    groups: &[Vec<String>],
+    alphabet: &Alphabet,
+) -> (Vec<Group>, Vec<(usize, AnswerError)>) {
+    let mut valid = Vec::new();
+    let mut rejected = Vec::new();
+    for (i, g) in groups.iter().enumerate() {
+        match Group::parse(g, alphabet) {
+            Ok(group) => valid.push(group),
+            Err(e) => rejected.push((i, e)),
+        }
     }
-    return yes_answers;
+    (valid, rejected)
 }
 
 /// Count number of unique YES answers in group
-fn count_common_in_group(groups: &Vec<Vec<String>>) -> Vec<usize> {
-    let mut common_answers = Vec::new();
-
-    for g in groups.iter() {
-        if g.len() == 1 {
-            common_answers.push(g[0].len())
-        } else {
-            let pass_answers: Vec<char> = g[0]
-                .chars()
-                .filter(|&c| g.iter().all(|s| s.contains(c)))
-                .collect();
-            common_answers.push(pass_answers.len());
+fn count_unique_in_group(groups: &[Group]) -> Vec<usize> {
+    groups.iter().map(|g| g.union().len()).collect()
+}
+
+/// Count number of common YES answers in group
+fn count_common_in_group(groups: &[Group]) -> Vec<usize> {
+    groups.iter().map(|g| g.intersection().len()).collect()
+}
+
+/// Count number of YES answers given by at least `k` passengers in group
+fn count_at_least_in_group(groups: &[Group], k: usize) -> Vec<usize> {
+    groups.iter().map(|g| g.at_least(k).len()).collect()
+}
+
+/// Optional command line arguments
+#[derive(Debug, Default, PartialEq)]
+struct Options {
+    /// Letters of the questions, instead of `a` to `z`
+    alphabet: Option<String>,
+    /// Also count the questions answered YES by at least this many in each group
+    at_least: Option<usize>,
+    /// Print the per question report
+    report: bool,
+    /// Files to write the per question statistics and the group sizes as CSV
+    csv: Option<String>,
+    sizes_csv: Option<String>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Options {
+        let usage = "Usage: aoc_day6 <file> [--alphabet <letters>] [--at-least <k>] [--report] \
+                     [--csv <file>] [--sizes-csv <file>]";
+        let mut options = Options::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || Some(args.next().expect(usage).clone());
+            match arg.as_str() {
+                "--alphabet" => options.alphabet = value(),
+                "--at-least" => {
+                    options.at_least = Some(value().unwrap().parse().expect(usage));
+                }
+                "--report" => options.report = true,
+                "--csv" => options.csv = value(),
+                "--sizes-csv" => options.sizes_csv = value(),
+                _ => panic!("{}", usage),
+            }
         }
+        options
     }
-    return common_answers;
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let split = split_passengers(&split_groups(&read_file(&args)));
+    let options = Options::parse(&args[2..]);
+    let alphabet = match &options.alphabet {
+        Some(letters) => Alphabet::new(letters).expect("Invalid alphabet"),
+        None => Alphabet::default(),
+    };
+    let (split, rejected) = parse_groups(
+        &split_passengers(&split_groups(&read_file(&args))),
+        &alphabet,
+    );
+    for (i, e) in rejected.iter() {
+        println!("Skipping group {}: {}", i + 1, e);
+    }
 
     // Total number of questions someone answered YES
     let yes_answers = count_unique_in_group(&split);
@@ -72,7 +135,29 @@ fn main() {
     println!(
         "Questions answered Yes by all - {:?}",
         common_answers.iter().sum::<usize>()
-    )
+    );
+
+    // Total number of questions at least k passengers answered YES
+    if let Some(k) = options.at_least {
+        let at_least_answers = count_at_least_in_group(&split, k);
+        println!(
+            "Questions answered Yes by at least {} - {:?}",
+            k,
+            at_least_answers.iter().sum::<usize>()
+        );
+    }
+
+    // Statistics per question
+    let report = Report::new(&split, &alphabet);
+    if options.report {
+        println!("\n{}", report);
+    }
+    if let Some(filename) = &options.csv {
+        fs::write(filename, report.to_csv()).expect("Something went wrong writing the file");
+    }
+    if let Some(filename) = &options.sizes_csv {
+        fs::write(filename, report.sizes_to_csv()).expect("Something went wrong writing the file");
+    }
 }
 
 #[cfg(test)]
@@ -113,15 +198,83 @@ b";
 
     #[test]
     fn test_count_unique_in_group() {
-        let split = split_passengers(&split_groups(GROUPS));
+        let (split, _) = parse_groups(
+            &split_passengers(&split_groups(GROUPS)),
+            &Alphabet::default(),
+        );
         let yes_answers = count_unique_in_group(&split);
         assert_eq!(yes_answers.iter().sum::<usize>(), 11)
     }
 
     #[test]
     fn test_count_common_in_group() {
-        let split = split_passengers(&split_groups(GROUPS));
+        let (split, _) = parse_groups(
+            &split_passengers(&split_groups(GROUPS)),
+            &Alphabet::default(),
+        );
         let common_answers = count_common_in_group(&split);
         assert_eq!(common_answers.iter().sum::<usize>(), 6)
     }
+
+    #[test]
+    fn test_count_at_least_in_group() {
+        let (split, _) = parse_groups(
+            &split_passengers(&split_groups(GROUPS)),
+            &Alphabet::default(),
+        );
+        assert_eq!(count_at_least_in_group(&split, 2), vec![0, 0, 1, 1, 0]);
+        assert_eq!(
+            count_at_least_in_group(&split, 1),
+            count_unique_in_group(&split)
+        );
+    }
+
+    #[test]
+    fn test_parse_groups_unknown_answer() {
+        let split = split_passengers(&split_groups("ab\na1\n\nab\n\na b"));
+        let (valid, rejected) = parse_groups(&split, &Alphabet::default());
+        assert_eq!(valid.len(), 1);
+        assert_eq!(
+            rejected,
+            vec![
+                (0, AnswerError::Unknown('1')),
+                (2, AnswerError::Unknown(' '))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_groups_messy_input() {
+        let messy = "abc  \r\n\r\n\r\na\r\nb\t\r\nc\r\n \r\nab\nac\n\n\n\na\na\na\na\n\nb\n\n";
+        let groups = split_groups(messy);
+        assert_eq!(groups, split_groups(GROUPS));
+
+        let (split, rejected) = parse_groups(&split_passengers(&groups), &Alphabet::default());
+        assert!(rejected.is_empty());
+        assert_eq!(count_unique_in_group(&split).iter().sum::<usize>(), 11);
+        assert_eq!(count_common_in_group(&split).iter().sum::<usize>(), 6);
+    }
+
+    #[test]
+    fn test_options_parse() {
+        let args: Vec<String> = [
+            "--report",
+            "--alphabet",
+            "xyz",
+            "--csv",
+            "out.csv",
+            "--at-least",
+            "2",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let options = Options::parse(&args);
+        assert!(options.report);
+        assert_eq!(options.alphabet.as_deref(), Some("xyz"));
+        assert_eq!(options.csv.as_deref(), Some("out.csv"));
+        assert_eq!(options.at_least, Some(2));
+        assert_eq!(options.sizes_csv, None);
+        assert_eq!(Options::parse(&[]), Options::default());
+    }
 }
@@ -0,0 +1,187 @@
+use crate::group::{Alphabet, Group};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// How many groups and passengers answered YES to a question
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuestionStats {
+    pub letter: char,
+    pub groups: usize,
+    pub people: usize,
+}
+
+/// Statistics of the customs declarations of all the groups
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub questions: Vec<QuestionStats>,
+    /// Number of groups of each size
+    pub group_sizes: BTreeMap<usize, usize>,
+    pub total_groups: usize,
+    pub total_people: usize,
+}
+
+impl Report {
+    pub fn new(groups: &[Group], alphabet: &Alphabet) -> Report {
+        let questions = alphabet
+            .letters()
+            .iter()
+            .enumerate()
+            .map(|(i, &letter)| QuestionStats {
+                letter,
+                groups: groups.iter().filter(|g| g.union().contains(i)).count(),
+                people: groups.iter().map(|g| g.count(i)).sum(),
+            })
+            .collect();
+
+        let mut group_sizes = BTreeMap::new();
+        for g in groups.iter() {
+            *group_sizes.entry(g.len()).or_insert(0) += 1;
+        }
+
+        Report {
+            questions,
+            group_sizes,
+            total_groups: groups.len(),
+            total_people: groups.iter().map(|g| g.len()).sum(),
+        }
+    }
+
+    /// Questions answered YES by the most passengers, all of them if there is a tie
+    pub fn most_common(&self) -> Vec<char> {
+        let most = self.questions.iter().map(|q| q.people).max();
+        self.with_people(most)
+    }
+
+    /// Questions answered YES by the fewest passengers, all of them if there is a tie
+    pub fn least_common(&self) -> Vec<char> {
+        let least = self.questions.iter().map(|q| q.people).min();
+        self.with_people(least)
+    }
+
+    fn with_people(&self, people: Option<usize>) -> Vec<char> {
+        self.questions
+            .iter()
+            .filter(|q| Some(q.people) == people)
+            .map(|q| q.letter)
+            .collect()
+    }
+
+    /// Per question statistics as CSV, one row per question
+    pub fn to_csv(&self) -> String {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer
+            .write_record(["question", "groups", "people"])
+            .unwrap();
+        for q in self.questions.iter() {
+            writer
+                .write_record([
+                    q.letter.to_string(),
+                    q.groups.to_string(),
+                    q.people.to_string(),
+                ])
+                .unwrap();
+        }
+        String::from_utf8(writer.into_inner().unwrap()).unwrap()
+    }
+
+    /// Distribution of group sizes as CSV, one row per size
+    pub fn sizes_to_csv(&self) -> String {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.write_record(["size", "groups"]).unwrap();
+        for (size, count) in self.group_sizes.iter() {
+            writer
+                .write_record([size.to_string(), count.to_string()])
+                .unwrap();
+        }
+        String::from_utf8(writer.into_inner().unwrap()).unwrap()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} groups, {} passengers",
+            self.total_groups, self.total_people
+        )?;
+        writeln!(f)?;
+        writeln!(f, "Question  Groups  People")?;
+        for q in self.questions.iter() {
+            writeln!(f, "{:>8}  {:>6}  {:>6}", q.letter, q.groups, q.people)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "Group size  Groups")?;
+        for (size, count) in self.group_sizes.iter() {
+            writeln!(f, "{:>10}  {:>6}", size, count)?;
+        }
+        writeln!(f)?;
+        let letters = |l: Vec<char>| l.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        writeln!(f, "Most common: {}", letters(self.most_common()).join(", "))?;
+        write!(
+            f,
+            "Least common: {}",
+            letters(self.least_common()).join(", ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn report(alphabet: &Alphabet) -> Report {
+        let groups: Vec<Group> = [vec!["abc"], vec!["a", "b", "c"], vec!["ab", "ac"]]
+            .iter()
+            .map(|g| Group::parse(g, alphabet).unwrap())
+            .collect();
+        Report::new(&groups, alphabet)
+    }
+
+    #[test]
+    fn test_report() {
+        let report = report(&Alphabet::new("abcd").unwrap());
+        assert_eq!(report.total_groups, 3);
+        assert_eq!(report.total_people, 6);
+        assert_eq!(
+            report.questions[0],
+            QuestionStats {
+                letter: 'a',
+                groups: 3,
+                people: 4
+            }
+        );
+        assert_eq!(report.questions[1].groups, 3);
+        assert_eq!(report.questions[1].people, 3);
+        assert_eq!(report.questions[3].groups, 0);
+        assert_eq!(report.group_sizes.get(&1), Some(&1));
+        assert_eq!(report.group_sizes.get(&2), Some(&1));
+        assert_eq!(report.group_sizes.get(&3), Some(&1));
+        assert_eq!(report.most_common(), vec!['a']);
+        assert_eq!(report.least_common(), vec!['d']);
+    }
+
+    #[test]
+    fn test_ties() {
+        let report = report(&Alphabet::new("abc").unwrap());
+        assert_eq!(report.least_common(), vec!['b', 'c']);
+    }
+
+    #[test]
+    fn test_to_csv() {
+        let report = report(&Alphabet::new("abc,").unwrap());
+        assert_eq!(
+            report.to_csv(),
+            "question,groups,people\na,3,4\nb,3,3\nc,3,3\n\",\",0,0\n"
+        );
+        assert_eq!(report.sizes_to_csv(), "size,groups\n1,1\n2,1\n3,1\n");
+    }
+
+    #[test]
+    fn test_display() {
+        let text = report(&Alphabet::new("abcd").unwrap()).to_string();
+        assert!(text.starts_with("3 groups, 6 passengers\n"));
+        assert!(text.contains("       a       3       4\n"));
+        assert!(text.ends_with("Most common: a\nLeast common: d"));
+    }
+}
@@ -0,0 +1,313 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// Error produced when a bag can, directly or not, contain itself
+#[derive(Debug, PartialEq)]
+pub struct CycleError {
+    /// Colours in the cycle, starting and ending with the same one
+    pub path: Vec<String>,
+}
+
+impl fmt::Display for CycleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "bag rules with a cycle: {}", self.path.join(" -> "))
+    }
+}
+
+impl std::error::Error for CycleError {}
+
+/// The rules of which bags contain which others, validated to have no cycles
+///
+/// Every colour mentioned in the rules is in the graph, even if it has no rule itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BagGraph {
+    /// Links each bag with its contents and how many of each
+    contains: HashMap<String, HashMap<String, usize>>,
+    /// Links each colour with those bags that can contain it directly
+    contained_by: HashMap<String, Vec<String>>,
+}
+
+impl BagGraph {
+    /// Builds the graph from the contents of each bag, checking there are no cycles
+    pub fn new(
+        mut contains: HashMap<String, HashMap<String, usize>>,
+    ) -> Result<BagGraph, CycleError> {
+        let mut contained_by: HashMap<String, Vec<String>> = HashMap::new();
+        for color in contains.keys() {
+            contained_by.entry(color.clone()).or_default();
+        }
+        for (color, contents) in contains.iter() {
+            for inner in contents.keys() {
+                let containers = contained_by.entry(inner.clone()).or_default();
+                if !containers.contains(color) {
+                    containers.push(color.clone());
+                }
+            }
+        }
+        for (color, containers) in contained_by.iter_mut() {
+            containers.sort();
+            contains.entry(color.clone()).or_default();
+        }
+
+        let graph = BagGraph {
+            contains,
+            contained_by,
+        };
+        match graph.find_cycle() {
+            Some(path) => Err(CycleError { path }),
+            None => Ok(graph),
+        }
+    }
+
+    /// Builds the graph from literal rules of each colour and its contents, for tests
+    #[cfg(test)]
+    pub fn from_rules(rules: &[(&str, &[(&str, usize)])]) -> Result<BagGraph, CycleError> {
+        BagGraph::new(
+            rules
+                .iter()
+                .map(|(color, contents)| {
+                    (
+                        color.to_string(),
+                        contents.iter().map(|(c, n)| (c.to_string(), *n)).collect(),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    /// All the colours in the graph, sorted
+    pub fn colors(&self) -> Vec<&str> {
+        let mut colors: Vec<&str> = self.contains.keys().map(|c| c.as_str()).collect();
+        colors.sort_unstable();
+        colors
+    }
+
+    /// Bags directly inside a bag of the given colour and how many of each
+    pub fn contents(&self, color: &str) -> Option<&HashMap<String, usize>> {
+        self.contains.get(color)
+    }
+
+    /// Bags that can directly contain a bag of the given colour
+    pub fn containers(&self, color: &str) -> Option<&[String]> {
+        self.contained_by.get(color).map(|c| c.as_slice())
+    }
+
+    /// Colours of all the bags that can eventually contain the given one, sorted
+    pub fn ancestors(&self, color: &str) -> Vec<&str> {
+        self.reachable(color, |c| {
+            self.containers(c)
+                .unwrap_or_default()
+                .iter()
+                .map(|c| c.as_str())
+                .collect()
+        })
+    }
+
+    /// Colours of all the bags that can eventually be inside the given one, sorted
+    pub fn descendants(&self, color: &str) -> Vec<&str> {
+        self.reachable(color, |c| self.sorted_contents(c))
+    }
+
+    /// Colours reachable from the given one following `next`, not including itself
+    fn reachable<'a>(&'a self, color: &str, next: impl Fn(&str) -> Vec<&'a str>) -> Vec<&'a str> {
+        let mut seen: HashSet<&str> = HashSet::new();
+        let mut pending: Vec<&str> = match self.contains.get_key_value(color) {
+            Some((c, _)) => next(c),
+            None => return Vec::new(),
+        };
+        while let Some(c) = pending.pop() {
+            if seen.insert(c) {
+                pending.extend(next(c));
+            }
+        }
+        let mut colors: Vec<&str> = seen.into_iter().collect();
+        colors.sort_unstable();
+        colors
+    }
+
+    /// The given colour and all the bags it can contain, with contents before containers
+    ///
+    /// Following this order, the value of each bag can be computed from the values of its
+    /// contents, which are already known.
+    pub fn contents_first(&self, color: &str) -> Vec<&str> {
+        let mut order = Vec::new();
+        let mut done: HashSet<&str> = HashSet::new();
+        let start = match self.contains.get_key_value(color) {
+            Some((c, _)) => c.as_str(),
+            None => return order,
+        };
+        let mut stack: Vec<(&str, Vec<&str>)> = vec![(start, self.sorted_contents(start))];
+        while let Some((c, pending)) = stack.last_mut() {
+            let c = *c;
+            match pending.pop() {
+                Some(inner) if !done.contains(inner) => {
+                    stack.push((inner, self.sorted_contents(inner)));
+                }
+                Some(_) => {}
+                None => {
+                    done.insert(c);
+                    order.push(c);
+                    stack.pop();
+                }
+            }
+        }
+        order
+    }
+
+    /// Looks for a cycle with a depth first search, returning its colours if found
+    ///
+    /// The search uses an explicit stack, so deep rule sets cannot overflow the call stack.
+    fn find_cycle(&self) -> Option<Vec<String>> {
+        #[derive(Clone, Copy, PartialEq)]
+        enum State {
+            InProgress,
+            Done,
+        }
+        let mut state: HashMap<&str, State> = HashMap::new();
+
+        for start in self.colors() {
+            if state.contains_key(start) {
+                continue;
+            }
+            // Each item is a colour in the current path and the contents still to visit
+            let mut stack: Vec<(&str, Vec<&str>)> = vec![(start, self.sorted_contents(start))];
+            state.insert(start, State::InProgress);
+
+            while let Some((color, pending)) = stack.last_mut() {
+                let color = *color;
+                match pending.pop() {
+                    None => {
+                        state.insert(color, State::Done);
+                        stack.pop();
+                    }
+                    Some(inner) => match state.get(inner) {
+                        Some(State::Done) => {}
+                        Some(State::InProgress) => {
+                            let from = stack.iter().position(|(c, _)| *c == inner).unwrap();
+                            let mut path: Vec<String> =
+                                stack[from..].iter().map(|(c, _)| c.to_string()).collect();
+                            path.push(inner.to_string());
+                            return Some(path);
+                        }
+                        None => {
+                            state.insert(inner, State::InProgress);
+                            stack.push((inner, self.sorted_contents(inner)));
+                        }
+                    },
+                }
+            }
+        }
+        None
+    }
+
+    /// Contents of the bag in reverse alphabetical order, so they are popped in order
+    fn sorted_contents(&self, color: &str) -> Vec<&str> {
+        let mut contents: Vec<&str> = self.contains[color].keys().map(|c| c.as_str()).collect();
+        contents.sort_unstable_by(|a, b| b.cmp(a));
+        contents
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let graph = BagGraph::from_rules(&[
+            ("light red", &[("bright white", 1), ("muted yellow", 2)]),
+            ("bright white", &[("shiny gold", 1)]),
+            ("muted yellow", &[("shiny gold", 2)]),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            graph.colors(),
+            vec!["bright white", "light red", "muted yellow", "shiny gold"]
+        );
+        assert_eq!(graph.contents("light red").unwrap()["muted yellow"], 2);
+        assert!(graph.contents("shiny gold").unwrap().is_empty());
+        assert_eq!(
+            graph.containers("shiny gold").unwrap(),
+            &["bright white".to_string(), "muted yellow".to_string()]
+        );
+        assert!(graph.containers("light red").unwrap().is_empty());
+        assert_eq!(graph.contents("dark blue"), None);
+        assert_eq!(graph.containers("dark blue"), None);
+    }
+
+    #[test]
+    fn test_traversals() {
+        let graph = BagGraph::from_rules(&[
+            ("light red", &[("bright white", 1), ("muted yellow", 2)]),
+            ("bright white", &[("shiny gold", 1)]),
+            ("muted yellow", &[("shiny gold", 2), ("faded blue", 9)]),
+            ("shiny gold", &[("faded blue", 3)]),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            graph.ancestors("shiny gold"),
+            vec!["bright white", "light red", "muted yellow"]
+        );
+        assert_eq!(
+            graph.descendants("muted yellow"),
+            vec!["faded blue", "shiny gold"]
+        );
+        assert!(graph.ancestors("light red").is_empty());
+        assert!(graph.descendants("faded blue").is_empty());
+        assert!(graph.ancestors("pale pink").is_empty());
+
+        let order = graph.contents_first("light red");
+        assert_eq!(order.len(), 5);
+        assert_eq!(order.last(), Some(&"light red"));
+        let position = |c| order.iter().position(|o| *o == c).unwrap();
+        assert!(position("faded blue") < position("shiny gold"));
+        assert!(position("shiny gold") < position("bright white"));
+        assert!(position("shiny gold") < position("muted yellow"));
+        assert!(graph.contents_first("pale pink").is_empty());
+    }
+
+    #[test]
+    fn test_self_cycle() {
+        let error = BagGraph::from_rules(&[("dark red", &[("dark red", 1)])]).unwrap_err();
+        assert_eq!(error.path, vec!["dark red", "dark red"]);
+    }
+
+    #[test]
+    fn test_indirect_cycle() {
+        let error = BagGraph::from_rules(&[
+            ("a bag", &[("b bag", 1), ("z bag", 1)]),
+            ("b bag", &[("c bag", 2)]),
+            ("c bag", &[("d bag", 3), ("b bag", 1)]),
+        ])
+        .unwrap_err();
+        assert_eq!(error.path, vec!["b bag", "c bag", "b bag"]);
+        assert_eq!(
+            error.to_string(),
+            "bag rules with a cycle: b bag -> c bag -> b bag"
+        );
+    }
+
+    #[test]
+    fn test_deep_chain() {
+        let n = 20_000;
+        let names: Vec<String> = (0..n).map(|i| format!("color {}", i)).collect();
+        let mut rules: HashMap<String, HashMap<String, usize>> = HashMap::new();
+        for i in 1..n {
+            rules
+                .entry(names[i - 1].clone())
+                .or_default()
+                .insert(names[i].clone(), 1);
+        }
+        assert!(BagGraph::new(rules.clone()).is_ok());
+
+        rules
+            .entry(names[n - 1].clone())
+            .or_default()
+            .insert(names[0].clone(), 1);
+        let error = BagGraph::new(rules).unwrap_err();
+        assert_eq!(error.path.len(), n + 1);
+    }
+}
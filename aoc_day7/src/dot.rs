@@ -0,0 +1,133 @@
+use crate::bag_graph::BagGraph;
+
+/// Which part of the rules to export
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope<'a> {
+    /// Every colour in the rules
+    All,
+    /// The colour and all the bags that can eventually contain it
+    Ancestors(&'a str),
+    /// The colour and all the bags that can eventually be inside it
+    Descendants(&'a str),
+}
+
+/// Quotes the colour so it can be used as a node id
+fn quote(color: &str) -> String {
+    format!("\"{}\"", color.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Export the bag rules in the Graphviz DOT language
+///
+/// There is an edge from each bag to each of its contents, labelled with how many of them
+/// it contains. Only the colours in the scope, and the edges between them, are exported.
+pub fn to_dot(graph: &BagGraph, scope: Scope) -> String {
+    let colors: Vec<&str> = match scope {
+        Scope::All => graph.colors(),
+        Scope::Ancestors(color) => with_color(color, graph, graph.ancestors(color)),
+        Scope::Descendants(color) => with_color(color, graph, graph.descendants(color)),
+    };
+
+    let mut output = String::from("digraph bags {\n");
+    for color in colors.iter() {
+        output.push_str(&format!("    {};\n", quote(color)));
+    }
+    for color in colors.iter() {
+        let mut contents: Vec<(&String, &usize)> = graph
+            .contents(color)
+            .into_iter()
+            .flatten()
+            .filter(|(inner, _)| colors.binary_search(&inner.as_str()).is_ok())
+            .collect();
+        contents.sort();
+        for (inner, num) in contents {
+            output.push_str(&format!(
+                "    {} -> {} [label=\"{}\"];\n",
+                quote(color),
+                quote(inner),
+                num
+            ));
+        }
+    }
+    output.push_str("}\n");
+    output
+}
+
+/// Adds the colour to the sorted list of related colours, if it is in the graph
+fn with_color<'a>(color: &'a str, graph: &BagGraph, mut colors: Vec<&'a str>) -> Vec<&'a str> {
+    if graph.contents(color).is_some() {
+        if let Err(i) = colors.binary_search(&color) {
+            colors.insert(i, color);
+        }
+    }
+    colors
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn graph() -> BagGraph {
+        BagGraph::from_rules(&[
+            ("light red", &[("bright white", 1), ("muted yellow", 2)]),
+            ("bright white", &[("shiny gold", 1)]),
+            ("shiny gold", &[("dark olive", 1)]),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let dot = to_dot(&graph(), Scope::All);
+        assert_eq!(
+            dot,
+            "digraph bags {
+    \"bright white\";
+    \"dark olive\";
+    \"light red\";
+    \"muted yellow\";
+    \"shiny gold\";
+    \"bright white\" -> \"shiny gold\" [label=\"1\"];
+    \"light red\" -> \"bright white\" [label=\"1\"];
+    \"light red\" -> \"muted yellow\" [label=\"2\"];
+    \"shiny gold\" -> \"dark olive\" [label=\"1\"];
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_to_dot_ancestors() {
+        let dot = to_dot(&graph(), Scope::Ancestors("shiny gold"));
+        assert!(dot.contains("\"light red\" -> \"bright white\" [label=\"1\"];"));
+        assert!(dot.contains("\"bright white\" -> \"shiny gold\" [label=\"1\"];"));
+        assert!(!dot.contains("muted yellow"));
+        assert!(!dot.contains("dark olive"));
+    }
+
+    #[test]
+    fn test_to_dot_descendants() {
+        let dot = to_dot(&graph(), Scope::Descendants("shiny gold"));
+        assert_eq!(
+            dot,
+            "digraph bags {
+    \"dark olive\";
+    \"shiny gold\";
+    \"shiny gold\" -> \"dark olive\" [label=\"1\"];
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_to_dot_unknown_color() {
+        let dot = to_dot(&graph(), Scope::Descendants("pale pink"));
+        assert_eq!(dot, "digraph bags {\n}\n");
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("shiny gold"), "\"shiny gold\"");
+        assert_eq!(quote("odd \"one\""), "\"odd \\\"one\\\"\"");
+    }
+}
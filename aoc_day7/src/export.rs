@@ -0,0 +1,243 @@
+use crate::bag_graph::BagGraph;
+use crate::rules::is_color;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+/// Error produced when importing bag rules from JSON
+#[derive(Debug)]
+pub enum ImportError {
+    Json(serde_json::Error),
+    /// A document that is not an object linking colours with contents
+    NotAnObject,
+    /// A colour that could not be written in a text rule
+    InvalidColor(String),
+    /// A bag whose contents are not an object of positive whole numbers
+    Rule {
+        color: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::Json(e) => write!(f, "JSON error: {}", e),
+            ImportError::NotAnObject => write!(f, "expected a JSON object with the rules"),
+            ImportError::InvalidColor(color) => write!(f, "invalid colour '{}'", color),
+            ImportError::Rule { color, message } => write!(f, "{} bags: {}", color, message),
+        }
+    }
+}
+
+impl Error for ImportError {}
+
+impl From<serde_json::Error> for ImportError {
+    fn from(e: serde_json::Error) -> Self {
+        ImportError::Json(e)
+    }
+}
+
+/// Contents of the bag sorted by colour
+fn sorted_contents<'a>(graph: &'a BagGraph, color: &str) -> Vec<(&'a String, &'a usize)> {
+    let mut contents: Vec<(&String, &usize)> =
+        graph.contents(color).into_iter().flatten().collect();
+    contents.sort();
+    contents
+}
+
+/// Export the rules as text, in the same format as the puzzle input
+///
+/// There is one rule per colour, sorted by colour, and the contents of each bag are also
+/// sorted. Bags with no contents are written as containing `no other bags`.
+pub fn to_text(graph: &BagGraph) -> String {
+    let mut output = String::new();
+    for color in graph.colors() {
+        let contents: Vec<String> = sorted_contents(graph, color)
+            .into_iter()
+            .map(|(inner, num)| match num {
+                1 => format!("1 {} bag", inner),
+                _ => format!("{} {} bags", num, inner),
+            })
+            .collect();
+        let contents = if contents.is_empty() {
+            "no other bags".to_string()
+        } else {
+            contents.join(", ")
+        };
+        output.push_str(&format!("{} bags contain {}.\n", color, contents));
+    }
+    output
+}
+
+/// Export the rules as a JSON object linking each colour with its contents
+pub fn to_json(graph: &BagGraph) -> String {
+    let mut rules = Map::new();
+    for color in graph.colors() {
+        let contents: Map<String, Value> = sorted_contents(graph, color)
+            .into_iter()
+            .map(|(inner, num)| (inner.clone(), Value::from(*num)))
+            .collect();
+        rules.insert(color.to_string(), Value::Object(contents));
+    }
+    let mut output = serde_json::to_string_pretty(&Value::Object(rules)).unwrap();
+    output.push('\n');
+    output
+}
+
+/// Import rules from a JSON object like the ones produced by `to_json`
+///
+/// Colours must follow the same grammar as in the text rules, so the rules can be
+/// exported back as text.
+pub fn from_json(data: &str) -> Result<HashMap<String, HashMap<String, usize>>, ImportError> {
+    let rules = match serde_json::from_str(data)? {
+        Value::Object(rules) => rules,
+        _ => return Err(ImportError::NotAnObject),
+    };
+
+    let mut contains = HashMap::new();
+    for (color, contents) in rules.into_iter() {
+        if !is_color(&color) {
+            return Err(ImportError::InvalidColor(color));
+        }
+        let contents = match contents {
+            Value::Object(contents) => contents,
+            _ => {
+                return Err(ImportError::Rule {
+                    color,
+                    message: "expected a JSON object with the contents".to_string(),
+                })
+            }
+        };
+        let mut bags = HashMap::new();
+        for (inner, num) in contents.into_iter() {
+            if !is_color(&inner) {
+                return Err(ImportError::InvalidColor(inner));
+            }
+            match num.as_u64().filter(|n| *n > 0) {
+                Some(n) => bags.insert(inner, n as usize),
+                None => {
+                    return Err(ImportError::Rule {
+                        color,
+                        message: format!("invalid number of {} bags: {}", inner, num),
+                    })
+                }
+            };
+        }
+        contains.insert(color, bags);
+    }
+    Ok(contains)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::rules::parse_rules;
+
+    const RULES: &str = "bright white bags contain 1 shiny gold bag.
+faded blue bags contain no other bags.
+light red bags contain 1 bright white bag, 2 muted yellow bags.
+muted yellow bags contain 9 faded blue bags, 2 shiny gold bags.
+shiny gold bags contain 3 faded blue bags.
+";
+
+    fn graph(text: &str) -> BagGraph {
+        let lines: Vec<String> = text.lines().map(|s| s.to_string()).collect();
+        BagGraph::new(parse_rules(&lines).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_to_text() {
+        let text = to_text(&graph(RULES));
+        assert_eq!(
+            text,
+            "bright white bags contain 1 shiny gold bag.
+faded blue bags contain no other bags.
+light red bags contain 1 bright white bag, 2 muted yellow bags.
+muted yellow bags contain 9 faded blue bags, 2 shiny gold bags.
+shiny gold bags contain 3 faded blue bags.
+"
+        );
+    }
+
+    #[test]
+    fn test_to_text_adds_missing_rules() {
+        let text = to_text(&graph("dark red bags contain 1 dark blue bag."));
+        assert_eq!(
+            text,
+            "dark blue bags contain no other bags.\ndark red bags contain 1 dark blue bag.\n"
+        );
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let original = graph(RULES);
+        let json = to_json(&original);
+        assert!(json.starts_with("{\n  \"bright white\": {\n    \"shiny gold\": 1\n  },"));
+
+        let imported = BagGraph::new(from_json(&json).unwrap()).unwrap();
+        assert_eq!(imported, original);
+        assert_eq!(to_text(&imported), RULES);
+    }
+
+    #[test]
+    fn test_no_other_round_trip() {
+        // Neither the text rules nor the JSON take `no other` as a colour
+        let lines = vec!["dark red bags contain 2 no other bags.".to_string()];
+        assert!(parse_rules(&lines).is_err());
+        let json = "{\"dark red\": {\"no other\": 2}, \"no other\": {}}";
+        assert_eq!(
+            from_json(json).unwrap_err().to_string(),
+            "invalid colour 'no other'"
+        );
+        let original = graph("dark red bags contain 2 no other red bags.");
+        let imported = BagGraph::new(from_json(&to_json(&original)).unwrap()).unwrap();
+        assert_eq!(imported, original);
+    }
+
+    #[test]
+    fn test_from_json_errors() {
+        assert!(matches!(from_json("{"), Err(ImportError::Json(_))));
+        assert_eq!(
+            from_json("[]").unwrap_err().to_string(),
+            "expected a JSON object with the rules"
+        );
+        assert_eq!(
+            from_json("{\"dark red\": 3}").unwrap_err().to_string(),
+            "dark red bags: expected a JSON object with the contents"
+        );
+        assert_eq!(
+            from_json("{\"dark red\": {\"dark blue\": 0}}")
+                .unwrap_err()
+                .to_string(),
+            "dark red bags: invalid number of dark blue bags: 0"
+        );
+        assert_eq!(
+            from_json("{\"dark red\": {\"dark blue\": \"two\"}}")
+                .unwrap_err()
+                .to_string(),
+            "dark red bags: invalid number of dark blue bags: \"two\""
+        );
+        for json in [
+            "{\"\": {}}",
+            "{\"dark  red\": {}}",
+            "{\"dark red bags\": {}}",
+            "{\"dark, red\": {}}",
+            "{\"no other\": {}}",
+            "{\"dark red\": {\"no other\": 1}}",
+            "{\"dark red\": {\"dark blue.\": 1}}",
+        ]
+        .iter()
+        {
+            assert!(matches!(from_json(json), Err(ImportError::InvalidColor(_))));
+        }
+        assert_eq!(
+            from_json("{\"dark red\": {\" dark blue\": 1}}")
+                .unwrap_err()
+                .to_string(),
+            "invalid colour ' dark blue'"
+        );
+    }
+}
@@ -0,0 +1,180 @@
+use aoc_common::Rng;
+
+/// Settings for generating random, acyclic bag rules
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Generator {
+    /// Number of colours, each with its own rule
+    pub colors: usize,
+    /// Maximum number of levels of bags inside any bag
+    pub depth: usize,
+    /// Maximum number of different colours inside each bag
+    pub fan_out: usize,
+    /// Maximum number of bags of each colour inside each bag, taking 0 as 1
+    pub max_count: usize,
+    /// Seed of the random number generator, so the same settings give the same rules
+    pub seed: u64,
+}
+
+impl Default for Generator {
+    fn default() -> Self {
+        Generator {
+            colors: 600,
+            depth: 10,
+            fan_out: 4,
+            max_count: 5,
+            seed: 7,
+        }
+    }
+}
+
+impl Generator {
+    /// Name of the i-th colour, with two words like in the puzzle input
+    pub fn color(i: usize) -> String {
+        format!("shade{} color{}", i / 100, i % 100)
+    }
+
+    /// Generate the rules, one per line
+    ///
+    /// The colours are split in `depth + 1` levels and bags only contain colours from
+    /// deeper levels, so there are no cycles. The first colour inside each bag is always
+    /// from the next level, so bags in the first level are nested exactly `depth` deep,
+    /// unless `fan_out` is 0 or there are fewer colours than levels.
+    ///
+    /// Levels are worked out with 128 bits, so any depth and number of colours that fit in
+    /// a `usize` work.
+    pub fn rules(&self) -> Vec<String> {
+        let mut rng = Rng::new(self.seed);
+        let mut next = |m: usize| rng.below(m);
+        let colors = self.colors as u128;
+        let levels = self.depth as u128 + 1;
+        let max_count = self.max_count.max(1);
+        let level_start = |level: u128| (level * colors).div_ceil(levels).min(colors) as usize;
+
+        (0..self.colors)
+            .map(|i| {
+                let level = i as u128 * levels / colors;
+                let (deeper, after) = (level_start(level + 1), level_start(level + 2));
+                let mut inner: Vec<usize> = Vec::new();
+                if deeper < self.colors && self.fan_out > 0 {
+                    let first = match after - deeper {
+                        0 => deeper + next(self.colors - deeper),
+                        n => deeper + next(n),
+                    };
+                    inner.push(first);
+                    // More draws than deeper colours could only add duplicates
+                    for _ in 0..next(self.fan_out).min(self.colors - deeper) {
+                        inner.push(deeper + next(self.colors - deeper));
+                    }
+                }
+                inner.sort_unstable();
+                inner.dedup();
+                if inner.is_empty() {
+                    return format!("{} bags contain no other bags.", Generator::color(i));
+                }
+                let contents: Vec<String> = inner
+                    .iter()
+                    .map(|&j| match 1 + next(max_count) {
+                        1 => format!("1 {} bag", Generator::color(j)),
+                        num => format!("{} {} bags", num, Generator::color(j)),
+                    })
+                    .collect();
+                format!(
+                    "{} bags contain {}.",
+                    Generator::color(i),
+                    contents.join(", ")
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::bag_graph::BagGraph;
+    use crate::query::depth;
+    use crate::rules::parse_rules;
+
+    #[test]
+    fn test_rules() {
+        let generator = Generator {
+            colors: 200,
+            depth: 6,
+            fan_out: 3,
+            max_count: 4,
+            seed: 42,
+        };
+        let rules = generator.rules();
+        assert_eq!(rules.len(), 200);
+        assert_eq!(rules, generator.rules());
+        assert_ne!(
+            rules,
+            Generator {
+                seed: 1,
+                ..generator
+            }
+            .rules()
+        );
+
+        let graph = BagGraph::new(parse_rules(&rules).unwrap()).unwrap();
+        assert_eq!(graph.colors().len(), 200);
+        let deepest = graph
+            .colors()
+            .iter()
+            .map(|c| depth(&graph, c).unwrap())
+            .max();
+        assert_eq!(deepest, Some(6));
+        for color in graph.colors() {
+            let contents = graph.contents(color).unwrap();
+            assert!(contents.len() <= 3);
+            assert!(contents.values().all(|n| (1..=4).contains(n)));
+        }
+    }
+
+    #[test]
+    fn test_small_rules() {
+        let generator = Generator {
+            colors: 3,
+            depth: 10,
+            ..Generator::default()
+        };
+        let rules = generator.rules();
+        assert_eq!(rules.len(), 3);
+        assert!(BagGraph::new(parse_rules(&rules).unwrap()).is_ok());
+        assert!(Generator {
+            colors: 0,
+            ..generator
+        }
+        .rules()
+        .is_empty());
+    }
+
+    #[test]
+    fn test_huge_settings() {
+        let generator = Generator {
+            colors: 50,
+            depth: usize::MAX,
+            fan_out: usize::MAX,
+            max_count: usize::MAX,
+            seed: 3,
+        };
+        let rules = generator.rules();
+        let graph = BagGraph::new(parse_rules(&rules).unwrap()).unwrap();
+        assert_eq!(graph.colors().len(), 50);
+        assert!(depth(&graph, &Generator::color(0)).unwrap() < 50);
+    }
+
+    #[test]
+    fn test_zero_max_count() {
+        let rules = Generator {
+            max_count: 0,
+            ..Generator::default()
+        }
+        .rules();
+        let graph = BagGraph::new(parse_rules(&rules).unwrap()).unwrap();
+        for color in graph.colors() {
+            assert!(graph.contents(color).unwrap().values().all(|n| *n == 1));
+        }
+    }
+}
@@ -1,98 +1,291 @@
-use regex::Regex;
+mod bag_graph;
+mod dot;
+mod export;
+mod generate;
+mod query;
+mod rules;
+
+use bag_graph::BagGraph;
+use dot::{to_dot, Scope};
+use export::{from_json, to_json, to_text};
+use generate::Generator;
+use num_bigint::BigUint;
+use rules::{parse_rules, RulesError};
 use std::collections::HashMap;
 use std::env;
 use std::fs;
 
-/// Read file into vector of strings
-fn read_file(args: &[String]) -> Vec<String> {
+/// Read file into a string
+fn read_file(args: &[String]) -> String {
     let filename = &args[1];
     println!("Reading {}", &args[1]);
 
-    fs::read_to_string(filename)
-        .expect("Something went wrong reading the file")
-        .trim_end()
-        .split("\n")
-        .map(|s| s.to_string())
+    fs::read_to_string(filename).expect("Something went wrong reading the file")
+}
+
+/// Read the colours and their contents into a bag graph
+///
+/// The graph links the bags with their potential contents and each colour with those
+/// bags that can contain them. It fails if any rule is malformed, if a colour has more
+/// than one rule or if any bag can end up containing itself.
+fn get_policies(policies: Vec<String>) -> Result<BagGraph, RulesError> {
+    Ok(BagGraph::new(parse_rules(&policies)?)?)
+}
+
+/// Load the rules, choosing the format based on the file extension
+///
+/// Files ending in `.json` are imported, anything else is read as one rule per line.
+fn load_policies(filename: &str, raw: &str) -> Result<BagGraph, RulesError> {
+    if filename.ends_with(".json") {
+        Ok(BagGraph::new(from_json(raw)?)?)
+    } else {
+        get_policies(raw.lines().map(|s| s.to_string()).collect())
+    }
+}
+
+/// Provide a list of color that can eventually contain the input color
+fn who_contains_me(color: &str, graph: &BagGraph) -> Vec<String> {
+    graph
+        .ancestors(color)
+        .iter()
+        .map(|c| c.to_string())
         .collect()
 }
 
-/// Read the colours and their contents in two hash maps
+/// Number type the bags can be counted with
+trait BagCount: Sized {
+    fn one() -> Self;
+
+    /// Adds `num` times `other` to this count, or `None` if the result does not fit
+    fn add_times(self, num: usize, other: &Self) -> Option<Self>;
+}
+
+impl BagCount for usize {
+    fn one() -> Self {
+        1
+    }
+
+    fn add_times(self, num: usize, other: &Self) -> Option<Self> {
+        num.checked_mul(*other)?.checked_add(self)
+    }
+}
+
+impl BagCount for BigUint {
+    fn one() -> Self {
+        BigUint::from(1u8)
+    }
+
+    fn add_times(self, num: usize, other: &Self) -> Option<Self> {
+        Some(self + other * num)
+    }
+}
+
+/// Count all individual bags that can fit within a bag, including itself
 ///
-/// One links the bags with its potential contents. The other links each colour with
-/// those bags that can contain them.
-fn get_policies(
-    policies: Vec<String>,
-) -> (
-    HashMap<String, HashMap<String, usize>>,
-    HashMap<String, Vec<String>>,
-) {
-    let mut contains: HashMap<String, HashMap<String, usize>> = HashMap::new();
-    let mut is_contained_by: HashMap<String, Vec<String>> = HashMap::new();
-    let rfirst = Regex::new(r"^(\w+\s\w+) bags contain").unwrap();
-    let rcontained = Regex::new(r"(\d+) (\w+\s\w+) bag?").unwrap();
-
-    for pol in policies.iter() {
-        let color = rfirst.captures(pol).unwrap()[1].to_string();
-        let contents = contains.entry(color.clone()).or_insert(HashMap::new());
-
-        for bags in rcontained.captures_iter(pol) {
-            contents
-                .entry(bags[2].to_string())
-                .or_insert(bags[1].parse().unwrap());
-
-            let contained = is_contained_by
-                .entry(bags[2].to_string())
-                .or_insert(Vec::new());
-            if !contained.contains(&color) {
-                contained.push(color.clone())
-            }
+/// Each colour is counted only once, from the counts of its contents, so shared contents
+/// are not traversed again. Returns `None` if the count does not fit in the number type.
+fn count_bags<T: BagCount>(color: &str, graph: &BagGraph) -> Option<T> {
+    let mut counts: HashMap<&str, T> = HashMap::new();
+    for c in graph.contents_first(color) {
+        let mut nbags = T::one();
+        for (inner, num) in graph.contents(c).into_iter().flatten() {
+            nbags = nbags.add_times(*num, &counts[inner.as_str()])?;
         }
+        counts.insert(c, nbags);
     }
-    return (contains, is_contained_by);
+    Some(counts.remove(color).unwrap_or_else(T::one))
 }
 
-/// Provide a list of color that can eventually contain the input color
-fn who_contains_me(color: &str, is_contained_by: &HashMap<String, Vec<String>>) -> Vec<String> {
-    let mut colors = is_contained_by[color].clone();
-    for c in is_contained_by[color].iter() {
-        if is_contained_by.contains_key(c) {
-            colors.extend(who_contains_me(c, &is_contained_by))
+/// Count all individual bags that can fit within a bag, or `None` if there are too many
+fn how_many_bags(color: &str, graph: &BagGraph) -> Option<usize> {
+    count_bags(color, graph)
+}
+
+/// Count all individual bags that can fit within a bag, however many there are
+fn how_many_bags_big(color: &str, graph: &BagGraph) -> BigUint {
+    count_bags(color, graph).unwrap()
+}
+
+/// What to do with the rules, given after the file name
+#[derive(Debug, PartialEq)]
+enum Command {
+    /// Answer the puzzle questions for a shiny gold bag
+    Summary,
+    /// Colours of the bags that can eventually contain the given one
+    Contains(String),
+    /// Colours of the bags that can eventually be inside the given one
+    ContainedBy(String),
+    /// Number of bags inside a bag of the given colour
+    Count(String),
+    /// Every way of nesting a bag inside another, with how many bags each accounts for
+    Path(String, String),
+    /// Number of levels of bags inside a bag of the given colour
+    Depth(String),
+    /// Export the rules as DOT to a file, maybe restricted to part of them
+    Dot(String, Option<(String, String)>),
+    /// Export the rules to a file, as JSON if it ends in `.json` or as text otherwise
+    Export(String),
+}
+
+impl Command {
+    /// Parses the command, where colours with spaces can be given as several arguments
+    ///
+    /// The two colours of `path` are separated by `--`, unless each is a single argument.
+    fn parse(args: &[String]) -> Command {
+        let usage = "Usage: aoc_day7 <file> [contains <color> | contained-by <color> | \
+                     count <color> | path <from> -- <to> | depth <color> | \
+                     dot <file> [--ancestors <color> | --descendants <color>] | \
+                     export <file>]";
+        let color = |words: &[String]| {
+            if words.is_empty() {
+                panic!("{}", usage);
+            }
+            words.join(" ")
+        };
+        match args {
+            [] => Command::Summary,
+            [command, rest @ ..] => match (command.as_str(), rest) {
+                ("contains", words) => Command::Contains(color(words)),
+                ("contained-by", words) => Command::ContainedBy(color(words)),
+                ("count", words) => Command::Count(color(words)),
+                ("depth", words) => Command::Depth(color(words)),
+                ("path", words) => match words.iter().position(|w| w == "--") {
+                    Some(i) => Command::Path(color(&words[..i]), color(&words[i + 1..])),
+                    None if words.len() == 2 => {
+                        Command::Path(color(&words[..1]), color(&words[1..]))
+                    }
+                    None => panic!("{}", usage),
+                },
+                ("dot", [filename]) => Command::Dot(filename.clone(), None),
+                ("export", [filename]) => Command::Export(filename.clone()),
+                ("dot", [filename, scope, words @ ..])
+                    if scope == "--ancestors" || scope == "--descendants" =>
+                {
+                    Command::Dot(filename.clone(), Some((scope.clone(), color(words))))
+                }
+                _ => panic!("{}", usage),
+            },
+        }
+    }
+
+    /// Runs the command on the rules, returning what to print
+    fn run(&self, graph: &BagGraph) -> String {
+        let unknown = |color: &str| graph.contents(color).is_none();
+        match self {
+            Command::Summary => {
+                let color = "shiny gold";
+
+                // How many colors can store shiny gold?
+                let contained_by = who_contains_me(color, graph);
+
+                // How many total bags need to fit within a shiny gold one?
+                format!(
+                    "How many colors can store shiny gold? - {}\n\
+                     How many total bags need to fit within a shiny gold one? - {}",
+                    contained_by.len(),
+                    count_inside(color, graph)
+                )
+            }
+            Command::Contains(color)
+            | Command::ContainedBy(color)
+            | Command::Count(color)
+            | Command::Depth(color)
+                if unknown(color) =>
+            {
+                format!("Unknown colour: {}", color)
+            }
+            Command::Path(from, to) if unknown(from) || unknown(to) => {
+                let color = if unknown(from) { from } else { to };
+                format!("Unknown colour: {}", color)
+            }
+            Command::Dot(_, Some((_, color))) if unknown(color) => {
+                format!("Unknown colour: {}", color)
+            }
+            Command::Contains(color) => who_contains_me(color, graph).join("\n"),
+            Command::ContainedBy(color) => graph.descendants(color).join("\n"),
+            Command::Count(color) => count_inside(color, graph),
+            Command::Depth(color) => query::depth(graph, color).unwrap().to_string(),
+            Command::Path(from, to) => query::paths(graph, from, to)
+                .iter()
+                .map(|p| format!("{} x {}", p.multiplicity, p.colors.join(" -> ")))
+                .collect::<Vec<String>>()
+                .join("\n"),
+            Command::Dot(filename, scope) => {
+                let scope = match scope {
+                    None => Scope::All,
+                    Some((scope, color)) if scope == "--ancestors" => Scope::Ancestors(color),
+                    Some((_, color)) => Scope::Descendants(color),
+                };
+                fs::write(filename, to_dot(graph, scope))
+                    .expect("Something went wrong writing the file");
+                format!("Exported rules to {}", filename)
+            }
+            Command::Export(filename) => {
+                let content = if filename.ends_with(".json") {
+                    to_json(graph)
+                } else {
+                    to_text(graph)
+                };
+                fs::write(filename, content).expect("Something went wrong writing the file");
+                format!("Exported rules to {}", filename)
+            }
         }
     }
-    colors.sort_by(|a, b| a.cmp(b));
-    colors.dedup();
-    return colors;
 }
 
-/// Count all individual bags that can fit within a bag
-fn how_many_bags(color: &str, contains: &HashMap<String, HashMap<String, usize>>) -> usize {
-    let mut nbags = 1;
-    for (c, num) in contains[color].iter() {
-        nbags += num * how_many_bags(&c, &contains);
+/// Number of bags inside a bag, not counting itself
+///
+/// If there are too many for a usize, they are counted again without limits.
+fn count_inside(color: &str, graph: &BagGraph) -> String {
+    match how_many_bags(color, graph) {
+        Some(nbags) => (nbags - 1).to_string(),
+        None => (how_many_bags_big(color, graph) - 1u8).to_string(),
     }
-    return nbags;
+}
+
+/// Read the settings of the random rules given after `generate`
+///
+/// A `--max-count` of 0 is taken as 1, as `Generator` does.
+fn parse_generator(args: &[String]) -> Generator {
+    let usage = "Usage: aoc_day7 generate [--colors <n>] [--depth <n>] [--fan-out <n>] \
+                 [--max-count <n>] [--seed <n>]";
+    let mut generator = Generator::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args.next().and_then(|v| v.parse().ok()).expect(usage);
+        match arg.as_str() {
+            "--colors" => generator.colors = value,
+            "--depth" => generator.depth = value,
+            "--fan-out" => generator.fan_out = value,
+            "--max-count" => generator.max_count = value,
+            "--seed" => generator.seed = value as u64,
+            _ => panic!("{}", usage),
+        }
+    }
+    generator
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
+    if args.get(1).map(|a| a.as_str()) == Some("generate") {
+        for rule in parse_generator(&args[2..]).rules() {
+            println!("{}", rule);
+        }
+        return;
+    }
+    let command = Command::parse(&args[2..]);
 
-    let (contain, is_contained_by) = get_policies(read_file(&args));
-    let color = "shiny gold";
-
-    // How many colors can store shiny gold?
-    let contained_by = who_contains_me(&color, &is_contained_by);
-    println!(
-        "How many colors can store shiny gold? - {}",
-        contained_by.len()
-    );
-
-    // How many total bags need to fit within a shiny gold one?
-    // We need to remove 1 to avoid coiunting the shiny one itself
-    let total_bags = how_many_bags(&color, &contain) - 1;
-    println!(
-        "How many total bags need to fit within a shiny gold one? - {}",
-        total_bags
-    )
+    let graph = match load_policies(&args[1], &read_file(&args)) {
+        Ok(graph) => graph,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let output = command.run(&graph);
+    if !output.is_empty() {
+        println!("{}", output);
+    }
 }
 
 #[cfg(test)]
@@ -120,27 +313,295 @@ dark violet bags contain no other bags.";
 
     #[test]
     fn test_get_policies() {
-        let raw = RULES.split("\n").map(|s| s.to_string()).collect();
-        let (contain, is_contained_by) = get_policies(raw);
-        assert_eq!(contain.len(), 9);
-        assert_eq!(is_contained_by.len(), 7);
+        let raw = RULES.split('\n').map(|s| s.to_string()).collect();
+        let graph = get_policies(raw).unwrap();
+        let colors = graph.colors();
+        assert_eq!(colors.len(), 9);
+        let contained = colors
+            .iter()
+            .filter(|c| !graph.containers(c).unwrap().is_empty());
+        assert_eq!(contained.count(), 7);
     }
 
     #[test]
     fn test_who_contains_me() {
-        let raw = RULES.split("\n").map(|s| s.to_string()).collect();
-        let (_, is_contained_by) = get_policies(raw);
+        let raw = RULES.split('\n').map(|s| s.to_string()).collect();
+        let graph = get_policies(raw).unwrap();
         let color = "shiny gold";
-        let contained_by = who_contains_me(&color, &is_contained_by);
+        let contained_by = who_contains_me(color, &graph);
         assert_eq!(contained_by.len(), 4)
     }
 
     #[test]
     fn test_how_many_bags() {
-        let raw = RULES_2.split("\n").map(|s| s.to_string()).collect();
-        let (contain, _) = get_policies(raw);
+        let raw = RULES_2.split('\n').map(|s| s.to_string()).collect();
+        let graph = get_policies(raw).unwrap();
         let color = "shiny gold";
-        let total_bags = how_many_bags(&color, &contain) - 1;
-        assert_eq!(total_bags, 126)
+        let total_bags = how_many_bags(color, &graph).unwrap() - 1;
+        assert_eq!(total_bags, 126);
+        assert_eq!(how_many_bags_big(color, &graph), BigUint::from(127u8));
+    }
+
+    #[test]
+    fn test_how_many_bags_overflow() {
+        // Each bag has 1000 of the next one, so the first contains 1000^10 = 10^30 bags
+        let raw: Vec<String> = (0..10)
+            .map(|i| format!("dark c{} bags contain 1000 dark c{} bags.", i, i + 1))
+            .collect();
+        let graph = get_policies(raw).unwrap();
+        assert_eq!(how_many_bags("dark c10", &graph), Some(1));
+        assert_eq!(how_many_bags("dark c0", &graph), None);
+
+        let expected = (0..=10u32).map(|i| BigUint::from(1000u32).pow(i)).sum();
+        assert_eq!(how_many_bags_big("dark c0", &graph), expected);
+    }
+
+    #[test]
+    fn test_load_policies() {
+        let raw = RULES.split('\n').map(|s| s.to_string()).collect();
+        let graph = get_policies(raw).unwrap();
+        assert_eq!(load_policies("rules.txt", RULES).unwrap(), graph);
+        assert_eq!(
+            load_policies("rules.json", &to_json(&graph)).unwrap(),
+            graph
+        );
+
+        let error = load_policies("rules.json", "{\"a b\": {\"a b\": 1}}").unwrap_err();
+        assert!(matches!(error, RulesError::Cycle(_)));
+        let error = load_policies("rules.json", RULES).unwrap_err();
+        assert!(matches!(error, RulesError::Import(_)));
+    }
+
+    #[test]
+    fn test_get_policies_cycle() {
+        let raw = "shiny gold bags contain 2 dark red bags.
+dark red bags contain 2 dark orange bags.
+dark orange bags contain 1 shiny gold bag, 3 dark blue bags.
+dark blue bags contain no other bags."
+            .split('\n')
+            .map(|s| s.to_string())
+            .collect();
+        match get_policies(raw).unwrap_err() {
+            RulesError::Cycle(error) => assert_eq!(
+                error.path,
+                vec!["dark orange", "shiny gold", "dark red", "dark orange"]
+            ),
+            e => panic!("Unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_get_policies_malformed() {
+        let raw = "shiny gold bags contain 2 dark red bags.
+dark red bags contain 2 dark orange bag."
+            .split('\n')
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(
+            get_policies(raw).unwrap_err().to_string(),
+            "invalid bag rules at line 2, column 37: 'bag' does not agree with 2 bag(s)"
+        );
+    }
+
+    #[test]
+    fn test_unknown_color() {
+        let raw = RULES.split('\n').map(|s| s.to_string()).collect();
+        let graph = get_policies(raw).unwrap();
+        assert!(who_contains_me("pale pink", &graph).is_empty());
+        assert_eq!(how_many_bags("pale pink", &graph), Some(1));
+    }
+
+    fn args(words: &[&str]) -> Vec<String> {
+        words.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(Command::parse(&[]), Command::Summary);
+        assert_eq!(
+            Command::parse(&args(&["count", "shiny", "gold"])),
+            Command::Count("shiny gold".to_string())
+        );
+        assert_eq!(
+            Command::parse(&args(&["contained-by", "shiny gold"])),
+            Command::ContainedBy("shiny gold".to_string())
+        );
+        assert_eq!(
+            Command::parse(&args(&["path", "light red", "shiny gold"])),
+            Command::Path("light red".to_string(), "shiny gold".to_string())
+        );
+        assert_eq!(
+            Command::parse(&args(&["path", "light", "red", "--", "shiny", "gold"])),
+            Command::Path("light red".to_string(), "shiny gold".to_string())
+        );
+        assert_eq!(
+            Command::parse(&args(&["export", "rules.json"])),
+            Command::Export("rules.json".to_string())
+        );
+        assert_eq!(
+            Command::parse(&args(&["dot", "out.dot", "--ancestors", "shiny", "gold"])),
+            Command::Dot(
+                "out.dot".to_string(),
+                Some(("--ancestors".to_string(), "shiny gold".to_string()))
+            )
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Usage")]
+    fn test_parse_command_missing_color() {
+        Command::parse(&args(&["depth"]));
+    }
+
+    #[test]
+    #[should_panic(expected = "Usage")]
+    fn test_parse_command_path_without_separator() {
+        Command::parse(&args(&["path", "light", "red", "shiny", "gold"]));
+    }
+
+    #[test]
+    #[should_panic(expected = "Usage")]
+    fn test_parse_command_path_missing_color() {
+        Command::parse(&args(&["path", "light", "red", "--"]));
+    }
+
+    #[test]
+    fn test_run_command() {
+        let raw = RULES.split('\n').map(|s| s.to_string()).collect();
+        let graph = get_policies(raw).unwrap();
+        let run = |words: &[&str]| Command::parse(&args(words)).run(&graph);
+
+        assert_eq!(
+            run(&[]),
+            "How many colors can store shiny gold? - 4
+How many total bags need to fit within a shiny gold one? - 32"
+        );
+        assert_eq!(
+            run(&["contains", "shiny", "gold"]),
+            "bright white\ndark orange\nlight red\nmuted yellow"
+        );
+        assert_eq!(
+            run(&["contained-by", "dark olive"]),
+            "dotted black\nfaded blue"
+        );
+        assert_eq!(run(&["count", "shiny gold"]), "32");
+        assert_eq!(run(&["depth", "light red"]), "4");
+        assert_eq!(
+            run(&["path", "light red", "dark olive"]),
+            "1 x light red -> bright white -> shiny gold -> dark olive
+4 x light red -> muted yellow -> shiny gold -> dark olive"
+        );
+        assert_eq!(run(&["path", "faded blue", "light red"]), "");
+        assert_eq!(run(&["count", "pale pink"]), "Unknown colour: pale pink");
+        assert_eq!(
+            run(&["path", "light red", "pale pink"]),
+            "Unknown colour: pale pink"
+        );
+        assert_eq!(
+            run(&["dot", "unused.dot", "--ancestors", "pale pink"]),
+            "Unknown colour: pale pink"
+        );
+    }
+
+    #[test]
+    fn test_parse_generator() {
+        assert_eq!(parse_generator(&[]), Generator::default());
+        let generator = parse_generator(&args(&["--depth", "3", "--seed", "11"]));
+        assert_eq!(generator.depth, 3);
+        assert_eq!(generator.seed, 11);
+        assert_eq!(generator.colors, Generator::default().colors);
+        assert_eq!(parse_generator(&args(&["--max-count", "0"])).max_count, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Usage")]
+    fn test_parse_generator_invalid() {
+        parse_generator(&args(&["--max-count", "-1"]));
+    }
+
+    /// Whether the bag can eventually contain the other, without any caching
+    fn oracle_contains(graph: &BagGraph, outer: &str, color: &str) -> bool {
+        graph
+            .contents(outer)
+            .unwrap()
+            .keys()
+            .any(|inner| inner == color || oracle_contains(graph, inner, color))
+    }
+
+    /// Count the bags inside the bag and itself, without any caching
+    fn oracle_count(graph: &BagGraph, color: &str) -> BigUint {
+        graph
+            .contents(color)
+            .unwrap()
+            .iter()
+            .fold(BigUint::from(1u8), |total, (inner, num)| {
+                total + oracle_count(graph, inner) * *num
+            })
+    }
+
+    #[test]
+    fn test_against_oracle() {
+        for seed in 0..20 {
+            let generator = Generator {
+                colors: 40,
+                depth: 5,
+                fan_out: 3,
+                max_count: 6,
+                seed,
+            };
+            let graph = get_policies(generator.rules()).unwrap();
+            for color in graph.colors() {
+                let expected: Vec<&str> = graph
+                    .colors()
+                    .into_iter()
+                    .filter(|outer| oracle_contains(&graph, outer, color))
+                    .collect();
+                assert_eq!(who_contains_me(color, &graph), expected);
+
+                let expected = oracle_count(&graph, color);
+                assert_eq!(how_many_bags_big(color, &graph), expected);
+                let small = how_many_bags(color, &graph).map(BigUint::from);
+                assert_eq!(small, Some(expected));
+            }
+        }
+    }
+
+    /// Compares the ways of counting on 10k generated colours nested 200 levels deep
+    #[test]
+    #[ignore]
+    fn bench_10k_colors() {
+        let generator = Generator {
+            colors: 10_000,
+            depth: 200,
+            fan_out: 4,
+            max_count: 9,
+            seed: 7,
+        };
+        let graph = get_policies(generator.rules()).unwrap();
+        let first = Generator::color(0);
+        let last = Generator::color(9_999);
+        let (first, last) = (first.as_str(), last.as_str());
+
+        let start = std::time::Instant::now();
+        let contained_by = who_contains_me(last, &graph);
+        let who = start.elapsed();
+
+        let start = std::time::Instant::now();
+        let small = how_many_bags(first, &graph);
+        let checked = start.elapsed();
+
+        let start = std::time::Instant::now();
+        let big = how_many_bags_big(first, &graph);
+        let unlimited = start.elapsed();
+
+        println!(
+            "who_contains_me: {:?} ({} colours), how_many_bags: {:?} (fits: {}), how_many_bags_big: {:?} ({} digits)",
+            who,
+            contained_by.len(),
+            checked,
+            small.is_some(),
+            unlimited,
+            big.to_string().len()
+        );
     }
 }
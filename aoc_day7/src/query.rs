@@ -0,0 +1,192 @@
+use crate::bag_graph::BagGraph;
+use num_bigint::BigUint;
+use std::collections::{HashMap, HashSet};
+
+/// A way of nesting one bag inside another, and how many bags it accounts for
+#[derive(Debug, Clone, PartialEq)]
+pub struct Path<'a> {
+    /// Colours from the outermost to the innermost bag
+    pub colors: Vec<&'a str>,
+    /// Product of the number of bags at each step
+    pub multiplicity: BigUint,
+}
+
+/// All the ways a bag of colour `from` can contain one of colour `to`, sorted by colours
+///
+/// Only bags that can eventually contain `to` are explored, so the time depends on the
+/// number of paths found rather than on every path starting at `from`.
+pub fn paths<'a>(graph: &'a BagGraph, from: &str, to: &str) -> Vec<Path<'a>> {
+    let mut found = Vec::new();
+    let leads_to: HashSet<&str> = graph.ancestors(to).into_iter().collect();
+    let start = match leads_to.get(from) {
+        Some(start) => *start,
+        None => return found,
+    };
+    // Each item is a colour in the current path, the bags it accounts for and the
+    // contents still to visit
+    let mut stack = vec![(
+        start,
+        BigUint::from(1u8),
+        leading_contents(graph, start, to, &leads_to),
+    )];
+    while let Some((_, multiplicity, pending)) = stack.last_mut() {
+        match pending.pop() {
+            None => {
+                stack.pop();
+            }
+            Some((inner, num)) => {
+                let product = &*multiplicity * num;
+                if inner == to {
+                    let mut colors: Vec<&str> = stack.iter().map(|(c, _, _)| *c).collect();
+                    colors.push(inner);
+                    found.push(Path {
+                        colors,
+                        multiplicity: product,
+                    });
+                } else {
+                    let contents = leading_contents(graph, inner, to, &leads_to);
+                    stack.push((inner, product, contents));
+                }
+            }
+        }
+    }
+    found
+}
+
+/// Contents of the bag that are `to` or lead to it, in reverse order so they are popped in
+/// order
+fn leading_contents<'a>(
+    graph: &'a BagGraph,
+    color: &str,
+    to: &str,
+    leads_to: &HashSet<&str>,
+) -> Vec<(&'a str, usize)> {
+    let mut contents: Vec<(&'a str, usize)> = graph
+        .contents(color)
+        .into_iter()
+        .flatten()
+        .map(|(inner, num)| (inner.as_str(), *num))
+        .filter(|(inner, _)| *inner == to || leads_to.contains(inner))
+        .collect();
+    contents.sort_unstable_by(|a, b| b.cmp(a));
+    contents
+}
+
+/// Number of levels of bags inside a bag of the given colour, 0 if it contains no bags
+///
+/// Returns `None` if the colour is not in the rules.
+pub fn depth(graph: &BagGraph, color: &str) -> Option<usize> {
+    let mut depths: HashMap<&str, usize> = HashMap::new();
+    for c in graph.contents_first(color) {
+        let deepest = graph
+            .contents(c)
+            .into_iter()
+            .flatten()
+            .map(|(inner, _)| depths[inner.as_str()] + 1)
+            .max()
+            .unwrap_or(0);
+        depths.insert(c, deepest);
+    }
+    depths.get(color).copied()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn graph() -> BagGraph {
+        BagGraph::from_rules(&[
+            ("light red", &[("bright white", 1), ("muted yellow", 2)]),
+            ("bright white", &[("shiny gold", 1)]),
+            ("muted yellow", &[("shiny gold", 2), ("faded blue", 9)]),
+            ("shiny gold", &[("faded blue", 3)]),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_paths() {
+        let graph = graph();
+        let found = paths(&graph, "light red", "faded blue");
+        let summary: Vec<(Vec<&str>, u32)> = found
+            .iter()
+            .map(|p| (p.colors.clone(), p.multiplicity.to_u32_digits()[0]))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    vec!["light red", "bright white", "shiny gold", "faded blue"],
+                    3
+                ),
+                (vec!["light red", "muted yellow", "faded blue"], 18),
+                (
+                    vec!["light red", "muted yellow", "shiny gold", "faded blue"],
+                    12
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_no_paths() {
+        let graph = graph();
+        assert!(paths(&graph, "faded blue", "light red").is_empty());
+        assert!(paths(&graph, "pale pink", "faded blue").is_empty());
+        assert!(paths(&graph, "light red", "pale pink").is_empty());
+    }
+
+    #[test]
+    fn test_no_paths_many_diamonds() {
+        // Each diamond doubles the paths from the start, none of which gets to "z z"
+        let mut contains: HashMap<String, HashMap<String, usize>> = HashMap::new();
+        for i in 0..40 {
+            let (top, bottom) = (format!("a x{}", i), format!("a x{}", i + 1));
+            for side in ["b", "c"].iter() {
+                let middle = format!("{} x{}", side, i);
+                contains
+                    .entry(top.clone())
+                    .or_default()
+                    .insert(middle.clone(), 1);
+                contains
+                    .entry(middle)
+                    .or_default()
+                    .insert(bottom.clone(), 1);
+            }
+        }
+        contains.entry("z z".to_string()).or_default();
+        let graph = BagGraph::new(contains).unwrap();
+        assert!(paths(&graph, "a x0", "z z").is_empty());
+        assert_eq!(paths(&graph, "a x0", "a x3").len(), 8);
+    }
+
+    #[test]
+    fn test_paths_deep_chain() {
+        let n = 50_000;
+        let names: Vec<String> = (0..n).map(|i| format!("c {}", i)).collect();
+        let mut contains: HashMap<String, HashMap<String, usize>> = HashMap::new();
+        for i in 1..n {
+            contains
+                .entry(names[i - 1].clone())
+                .or_default()
+                .insert(names[i].clone(), 1);
+        }
+        contains.entry(names[n - 1].clone()).or_default();
+        let graph = BagGraph::new(contains).unwrap();
+        let found = paths(&graph, "c 0", "c 49999");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].colors.len(), n);
+        assert_eq!(found[0].multiplicity, BigUint::from(1u8));
+        assert_eq!(depth(&graph, "c 0"), Some(n - 1));
+    }
+
+    #[test]
+    fn test_depth() {
+        let graph = graph();
+        assert_eq!(depth(&graph, "light red"), Some(3));
+        assert_eq!(depth(&graph, "muted yellow"), Some(2));
+        assert_eq!(depth(&graph, "faded blue"), Some(0));
+        assert_eq!(depth(&graph, "pale pink"), None);
+    }
+}
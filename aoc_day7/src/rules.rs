@@ -0,0 +1,458 @@
+use crate::bag_graph::CycleError;
+use crate::export::ImportError;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+/// What is wrong with a rule
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseErrorKind {
+    /// The text found does not match the grammar at that point
+    Expected(&'static str),
+    /// A number of bags that is zero or does not fit
+    InvalidNumber(String),
+    /// `bag` used with a number other than 1, or `bags` with 1
+    Plural { num: usize, found: String },
+    /// The same colour listed twice in the contents of a bag
+    DuplicateContent(String),
+    /// The same rule given again, identical to the first one
+    DuplicateRule { color: String, first_line: usize },
+    /// The same colour given again, with different contents
+    ConflictingRule { color: String, first_line: usize },
+    /// `no other` used as a colour, as it marks a bag with no contents
+    ReservedColor,
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseErrorKind::Expected(what) => write!(f, "expected {}", what),
+            ParseErrorKind::InvalidNumber(num) => write!(f, "invalid number of bags '{}'", num),
+            ParseErrorKind::Plural { num, found } => {
+                write!(f, "'{}' does not agree with {} bag(s)", found, num)
+            }
+            ParseErrorKind::DuplicateContent(color) => {
+                write!(f, "{} bags listed more than once", color)
+            }
+            ParseErrorKind::DuplicateRule { color, first_line } => write!(
+                f,
+                "duplicate rule for {} bags, first given on line {}",
+                color, first_line
+            ),
+            ParseErrorKind::ConflictingRule { color, first_line } => write!(
+                f,
+                "conflicting rule for {} bags, first given on line {}",
+                color, first_line
+            ),
+            ParseErrorKind::ReservedColor => write!(f, "'no other' is not a valid colour"),
+        }
+    }
+}
+
+/// Error produced when a rule does not follow the grammar, with its position
+///
+/// Lines and columns start at 1 and columns count characters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.kind
+        )
+    }
+}
+
+impl Error for ParseError {}
+
+/// Error produced when reading the bag rules
+#[derive(Debug)]
+pub enum RulesError {
+    Parse(ParseError),
+    Import(ImportError),
+    Cycle(CycleError),
+}
+
+impl fmt::Display for RulesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RulesError::Parse(e) => write!(f, "invalid bag rules at {}", e),
+            RulesError::Import(e) => write!(f, "invalid bag rules: {}", e),
+            RulesError::Cycle(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for RulesError {}
+
+impl From<ParseError> for RulesError {
+    fn from(e: ParseError) -> Self {
+        RulesError::Parse(e)
+    }
+}
+
+impl From<ImportError> for RulesError {
+    fn from(e: ImportError) -> Self {
+        RulesError::Import(e)
+    }
+}
+
+impl From<CycleError> for RulesError {
+    fn from(e: CycleError) -> Self {
+        RulesError::Cycle(e)
+    }
+}
+
+/// Reads a single rule, keeping track of the position within the line
+struct RuleParser<'a> {
+    text: &'a str,
+    pos: usize,
+    line: usize,
+}
+
+impl<'a> RuleParser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        self.error_at(self.pos, kind)
+    }
+
+    fn error_at(&self, pos: usize, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            line: self.line,
+            column: self.text[..pos].chars().count() + 1,
+            kind,
+        }
+    }
+
+    /// Consumes the literal text if it comes next
+    fn accept(&mut self, literal: &str) -> bool {
+        if self.rest().starts_with(literal) {
+            self.pos += literal.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, literal: &'static str, what: &'static str) -> Result<(), ParseError> {
+        if self.accept(literal) {
+            Ok(())
+        } else {
+            Err(self.error(ParseErrorKind::Expected(what)))
+        }
+    }
+
+    /// The word starting at the given position, made of letters and digits
+    fn word_at(&self, pos: usize) -> &'a str {
+        let rest = &self.text[pos..];
+        let end = rest
+            .find(|c: char| !c.is_alphanumeric())
+            .unwrap_or(rest.len());
+        &rest[..end]
+    }
+
+    /// Reads a colour, made of one or more words up to `bag` or `bags`
+    ///
+    /// `no other` is reserved, as it marks a bag with no contents.
+    fn color(&mut self) -> Result<&'a str, ParseError> {
+        let start = self.pos;
+        loop {
+            let word = self.word_at(self.pos);
+            if word.is_empty() || word == "bag" || word == "bags" {
+                return Err(self.error(ParseErrorKind::Expected("a colour")));
+            }
+            self.pos += word.len();
+            let next = self.pos + 1;
+            if !self.rest().starts_with(' ') || matches!(self.word_at(next), "bag" | "bags") {
+                let color = &self.text[start..self.pos];
+                if color == "no other" {
+                    return Err(self.error_at(start, ParseErrorKind::ReservedColor));
+                }
+                return Ok(color);
+            }
+            self.pos = next;
+        }
+    }
+
+    /// Reads a positive number of bags
+    fn number(&mut self) -> Result<usize, ParseError> {
+        let start = self.pos;
+        let digits = self.rest().find(|c: char| !c.is_ascii_digit());
+        let digits = &self.rest()[..digits.unwrap_or(self.rest().len())];
+        if digits.is_empty() {
+            return Err(self.error(ParseErrorKind::Expected("a number of bags")));
+        }
+        self.pos += digits.len();
+        match digits.parse() {
+            Ok(num) if num > 0 => Ok(num),
+            _ => Err(self.error_at(start, ParseErrorKind::InvalidNumber(digits.to_string()))),
+        }
+    }
+
+    /// Reads `bag` or `bags`, checking it agrees with the number
+    fn bags(&mut self, num: usize) -> Result<(), ParseError> {
+        let start = self.pos;
+        let word = self.word_at(start);
+        let expected = if num == 1 { "bag" } else { "bags" };
+        match word {
+            "bag" | "bags" if word == expected => {}
+            "bag" | "bags" => {
+                return Err(self.error(ParseErrorKind::Plural {
+                    num,
+                    found: word.to_string(),
+                }))
+            }
+            _ => return Err(self.error(ParseErrorKind::Expected("'bag' or 'bags'"))),
+        }
+        self.pos += word.len();
+        Ok(())
+    }
+
+    /// Reads a whole rule: `<colour> bags contain <contents>.`
+    ///
+    /// The contents are either `no other bags` or a list of `<number> <colour> bag(s)`
+    /// separated by `, `.
+    fn rule(&mut self) -> Result<(&'a str, HashMap<String, usize>), ParseError> {
+        let color = self.color()?;
+        self.expect(" bags contain ", "' bags contain '")?;
+
+        let mut contents = HashMap::new();
+        if !self.accept("no other bags.") {
+            loop {
+                let num = self.number()?;
+                self.expect(" ", "a space")?;
+                let start = self.pos;
+                let inner = self.color()?;
+                self.expect(" ", "a space")?;
+                self.bags(num)?;
+                if contents.insert(inner.to_string(), num).is_some() {
+                    return Err(
+                        self.error_at(start, ParseErrorKind::DuplicateContent(inner.to_string()))
+                    );
+                }
+                if self.accept(".") {
+                    break;
+                }
+                self.expect(", ", "',' or '.'")?;
+            }
+        }
+        if !self.rest().is_empty() {
+            return Err(self.error(ParseErrorKind::Expected("the end of the rule")));
+        }
+        Ok((color, contents))
+    }
+}
+
+/// Whether the text is a colour as read in the rules, like `shiny gold`
+pub fn is_color(text: &str) -> bool {
+    let mut parser = RuleParser {
+        text,
+        pos: 0,
+        line: 1,
+    };
+    matches!(parser.color(), Ok(color) if color == text)
+}
+
+/// Parses the rules, one per line, into the contents of each bag
+///
+/// Blank lines are ignored, as is whitespace at the end of a line. Colours can have any
+/// number of words, made of letters and digits and separated by single spaces. Each
+/// colour can only have one rule, and a bag can only list each colour once.
+pub fn parse_rules(
+    lines: &[String],
+) -> Result<HashMap<String, HashMap<String, usize>>, ParseError> {
+    let mut rules: HashMap<String, (usize, HashMap<String, usize>)> = HashMap::new();
+    for (i, text) in lines.iter().enumerate() {
+        let text = text.trim_end();
+        if text.is_empty() {
+            continue;
+        }
+        let mut parser = RuleParser {
+            text,
+            pos: 0,
+            line: i + 1,
+        };
+        let (color, contents) = parser.rule()?;
+        if let Some((first_line, first)) = rules.get(color) {
+            let color = color.to_string();
+            let first_line = *first_line;
+            let kind = if *first == contents {
+                ParseErrorKind::DuplicateRule { color, first_line }
+            } else {
+                ParseErrorKind::ConflictingRule { color, first_line }
+            };
+            return Err(parser.error_at(0, kind));
+        }
+        rules.insert(color.to_string(), (i + 1, contents));
+    }
+    Ok(rules
+        .into_iter()
+        .map(|(color, (_, contents))| (color, contents))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn parse(text: &str) -> Result<HashMap<String, HashMap<String, usize>>, ParseError> {
+        let lines: Vec<String> = text.split('\n').map(|s| s.to_string()).collect();
+        parse_rules(&lines)
+    }
+
+    fn error(text: &str) -> (usize, usize, ParseErrorKind) {
+        let e = parse(text).unwrap_err();
+        (e.line, e.column, e.kind)
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        let rules = parse(
+            "light red bags contain 1 bright white bag, 2 muted yellow bags.\r
+faded blue bags contain no other bags.
+
+shiny bags contain 10 very dark olive bags.  ",
+        )
+        .unwrap();
+        assert_eq!(rules.len(), 3);
+        assert_eq!(rules["light red"].len(), 2);
+        assert_eq!(rules["light red"]["bright white"], 1);
+        assert_eq!(rules["light red"]["muted yellow"], 2);
+        assert!(rules["faded blue"].is_empty());
+        assert_eq!(rules["shiny"]["very dark olive"], 10);
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert_eq!(
+            error("light red bags contains no other bags."),
+            (1, 10, ParseErrorKind::Expected("' bags contain '"))
+        );
+        assert_eq!(
+            error("bags contain no other bags."),
+            (1, 1, ParseErrorKind::Expected("a colour"))
+        );
+        assert_eq!(
+            error("light red bags contain 1 bright white bag"),
+            (1, 42, ParseErrorKind::Expected("',' or '.'"))
+        );
+        assert_eq!(
+            error("light red bags contain 1 bright white bag; 2 dark red bags."),
+            (1, 42, ParseErrorKind::Expected("',' or '.'"))
+        );
+        assert_eq!(
+            error("light red bags contain some bright white bags."),
+            (1, 24, ParseErrorKind::Expected("a number of bags"))
+        );
+        assert_eq!(
+            error("light red bags contain 2 bright  white bags."),
+            (1, 33, ParseErrorKind::Expected("a colour"))
+        );
+        assert_eq!(
+            error("light red bags contain no other bags. Really."),
+            (1, 38, ParseErrorKind::Expected("the end of the rule"))
+        );
+    }
+
+    #[test]
+    fn test_reserved_color() {
+        assert_eq!(
+            error("no other bags contain no other bags."),
+            (1, 1, ParseErrorKind::ReservedColor)
+        );
+        assert_eq!(
+            error("dark red bags contain 2 no other bags."),
+            (1, 25, ParseErrorKind::ReservedColor)
+        );
+        assert!(
+            parse("no other red bags contain 1 other bag.\nother bags contain no other bags.")
+                .is_ok()
+        );
+        assert!(!is_color("no other"));
+        assert!(is_color("no other red"));
+    }
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(
+            error("light red bags contain 0 bright white bags."),
+            (1, 24, ParseErrorKind::InvalidNumber("0".to_string()))
+        );
+        assert_eq!(
+            error("a b bags contain 99999999999999999999999 c d bags."),
+            (
+                1,
+                18,
+                ParseErrorKind::InvalidNumber("99999999999999999999999".to_string())
+            )
+        );
+        assert_eq!(
+            error("light red bags contain 2 bright white bag."),
+            (
+                1,
+                39,
+                ParseErrorKind::Plural {
+                    num: 2,
+                    found: "bag".to_string()
+                }
+            )
+        );
+        assert_eq!(
+            error("light red bags contain 1 bright white bags."),
+            (
+                1,
+                39,
+                ParseErrorKind::Plural {
+                    num: 1,
+                    found: "bags".to_string()
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn test_duplicates() {
+        assert_eq!(
+            error("light red bags contain 1 dark red bag, 2 dark red bags."),
+            (
+                1,
+                42,
+                ParseErrorKind::DuplicateContent("dark red".to_string())
+            )
+        );
+        assert_eq!(
+            error(
+                "light red bags contain 1 dark red bag.
+dark red bags contain no other bags.
+light red bags contain 1 dark red bag."
+            ),
+            (
+                3,
+                1,
+                ParseErrorKind::DuplicateRule {
+                    color: "light red".to_string(),
+                    first_line: 1
+                }
+            )
+        );
+        let e = parse(
+            "light red bags contain 1 dark red bag.
+light red bags contain 2 dark red bags.",
+        )
+        .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "line 2, column 1: conflicting rule for light red bags, first given on line 1"
+        );
+    }
+}
@@ -0,0 +1,405 @@
+use crate::opcodes::Action;
+use crate::program::Program;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// Where the program goes after running an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Successor {
+    /// Another instruction, by position
+    Instruction(usize),
+    /// The position right after the last instruction, where the program terminates
+    End,
+    /// Before the first instruction or past the end
+    OutOfBounds,
+    /// Only known when running it, for conditional registered opcodes like `jz`
+    Dynamic,
+}
+
+/// The control flow graph of a program and what can be learnt from it without running it
+///
+/// Each instruction has a single successor, so every strongly connected component with
+/// more than one instruction, or with an instruction jumping to itself, is a simple cycle:
+/// an infinite loop if the program ever gets there. Registered opcodes that are not
+/// conditional, like `out`, always go the same way, and `hlt` goes to the end. The
+/// analysis does not follow the flow past conditional ones, as where they go depends on
+/// the accumulator, so once the program gets to one it is unknown whether the
+/// instructions not reached yet ever run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+    pub successors: Vec<Successor>,
+    /// Whether running the program from the start surely gets to each instruction
+    pub reachable: Vec<bool>,
+    /// Whether running the program from the start gets to a conditional registered opcode
+    pub reaches_dynamic: bool,
+    /// Positions in each loop, in the order they run, starting from the lowest one
+    pub loops: Vec<Vec<usize>>,
+}
+
+impl Analysis {
+    pub fn new(program: &Program) -> Analysis {
+        let len = program.len();
+        let successors: Vec<Successor> = program
+            .instructions
+            .iter()
+            .enumerate()
+            .map(|(pc, instruction)| {
+                let jump = match instruction.fixed_action() {
+                    Some(Action::Jump(jump)) => jump,
+                    Some(Action::Output(_)) => 1,
+                    Some(Action::Halt) => return Successor::End,
+                    Some(Action::Overflow) | None => return Successor::Dynamic,
+                };
+                match (pc as i64)
+                    .checked_add(jump)
+                    .and_then(|next| usize::try_from(next).ok())
+                {
+                    Some(next) if next < len => Successor::Instruction(next),
+                    Some(next) if next == len => Successor::End,
+                    _ => Successor::OutOfBounds,
+                }
+            })
+            .collect();
+
+        let mut reachable = vec![false; len];
+        let mut pc = Successor::Instruction(0);
+        while let Successor::Instruction(current) = pc {
+            if current >= len || reachable[current] {
+                break;
+            }
+            reachable[current] = true;
+            pc = successors[current];
+        }
+
+        Analysis {
+            loops: find_loops(&successors),
+            successors,
+            reachable,
+            reaches_dynamic: pc == Successor::Dynamic,
+        }
+    }
+
+    /// Positions of the instructions that are not surely reached, sorted
+    fn not_reached(&self) -> Vec<usize> {
+        (0..self.reachable.len())
+            .filter(|pc| !self.reachable[*pc])
+            .collect()
+    }
+
+    /// Positions of the instructions that can never run, sorted
+    pub fn unreachable(&self) -> Vec<usize> {
+        if self.reaches_dynamic {
+            Vec::new()
+        } else {
+            self.not_reached()
+        }
+    }
+
+    /// Positions of the instructions that may run or not, depending on conditional opcodes
+    pub fn unknown(&self) -> Vec<usize> {
+        if self.reaches_dynamic {
+            self.not_reached()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Export the control flow graph in the Graphviz DOT language
+    ///
+    /// Nodes are labelled with the line and instruction. Unreachable instructions are
+    /// dashed, those that may be unreachable are dotted, those in a loop are red and
+    /// conditional registered opcodes, with no known successor, are diamonds.
+    pub fn to_dot(&self, program: &Program) -> String {
+        let in_loop = self.in_loop();
+        let mut output = String::from("digraph program {\n    node [shape=box];\n");
+        output.push_str("    end [label=\"end\", shape=doublecircle];\n");
+        if self.successors.contains(&Successor::OutOfBounds) {
+            output.push_str("    out [label=\"out of bounds\", shape=octagon];\n");
+        }
+        for (pc, instruction) in program.instructions.iter().enumerate() {
+            let mut style = Vec::new();
+            if !self.reachable[pc] && self.reaches_dynamic {
+                style.push("style=dotted".to_string());
+            } else if !self.reachable[pc] {
+                style.push("style=dashed".to_string());
+            }
+            if in_loop[pc] {
+                style.push("color=red".to_string());
+            }
+            if self.successors[pc] == Successor::Dynamic {
+                style.push("shape=diamond".to_string());
+            }
+            let style: String = style.iter().map(|s| format!(", {}", s)).collect();
+            output.push_str(&format!(
+                "    n{} [label=\"{}: {}\"{}];\n",
+                pc,
+                pc + 1,
+                instruction,
+                style
+            ));
+        }
+        for (pc, successor) in self.successors.iter().enumerate() {
+            let to = match successor {
+                Successor::Instruction(next) => format!("n{}", next),
+                Successor::End => "end".to_string(),
+                Successor::OutOfBounds => "out".to_string(),
+                Successor::Dynamic => continue,
+            };
+            output.push_str(&format!("    n{} -> {};\n", pc, to));
+        }
+        output.push_str("}\n");
+        output
+    }
+
+    /// Whether each instruction is part of a loop
+    fn in_loop(&self) -> Vec<bool> {
+        let mut in_loop = vec![false; self.successors.len()];
+        for pc in self.loops.iter().flatten() {
+            in_loop[*pc] = true;
+        }
+        in_loop
+    }
+}
+
+/// Finds the cycles of the graph, following the successors from each instruction in turn
+///
+/// A walk stops when it gets to an instruction already seen. If it was seen in this same
+/// walk, the instructions from there on form a new cycle. Each instruction is walked over
+/// once, so this takes linear time.
+fn find_loops(successors: &[Successor]) -> Vec<Vec<usize>> {
+    // The walk each instruction was first seen in, counting from 1
+    let mut walk_of = vec![0; successors.len()];
+    let mut loops = Vec::new();
+    for start in 0..successors.len() {
+        if walk_of[start] != 0 {
+            continue;
+        }
+        let walk = start + 1;
+        let mut path = Vec::new();
+        let mut pc = start;
+        loop {
+            if walk_of[pc] != 0 {
+                if walk_of[pc] == walk {
+                    let from = path.iter().position(|p| *p == pc).unwrap();
+                    loops.push(path.split_off(from));
+                }
+                break;
+            }
+            walk_of[pc] = walk;
+            path.push(pc);
+            match successors[pc] {
+                Successor::Instruction(next) => pc = next,
+                _ => break,
+            }
+        }
+    }
+    for cycle in loops.iter_mut() {
+        let lowest = (0..cycle.len()).min_by_key(|i| cycle[*i]).unwrap();
+        cycle.rotate_left(lowest);
+    }
+    loops.sort();
+    loops
+}
+
+/// Line numbers for the sorted positions, joining consecutive ones like `3-10`
+fn line_ranges(pcs: &[usize]) -> String {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for pc in pcs.iter() {
+        match ranges.last_mut() {
+            Some((_, last)) if *last + 1 == *pc => *last = *pc,
+            _ => ranges.push((*pc, *pc)),
+        }
+    }
+    let ranges: Vec<String> = ranges
+        .iter()
+        .map(|(first, last)| {
+            if first == last {
+                (first + 1).to_string()
+            } else {
+                format!("{}-{}", first + 1, last + 1)
+            }
+        })
+        .collect();
+    ranges.join(", ")
+}
+
+impl fmt::Display for Analysis {
+    /// Summary of the analysis, using line numbers counting from 1
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (unreachable, unknown) = (self.unreachable(), self.unknown());
+        writeln!(
+            f,
+            "{} instructions, {} reachable from the start",
+            self.successors.len(),
+            self.successors.len() - unreachable.len() - unknown.len()
+        )?;
+        if !unreachable.is_empty() {
+            writeln!(f, "Unreachable lines: {}", line_ranges(&unreachable))?;
+        }
+        if !unknown.is_empty() {
+            writeln!(
+                f,
+                "Lines reached or not depending on conditional opcodes: {}",
+                line_ranges(&unknown)
+            )?;
+        }
+        let dynamic: Vec<usize> = (0..self.successors.len())
+            .filter(|pc| self.successors[*pc] == Successor::Dynamic)
+            .collect();
+        if !dynamic.is_empty() {
+            writeln!(
+                f,
+                "Not followed past conditional opcodes at lines: {}",
+                line_ranges(&dynamic)
+            )?;
+        }
+        for cycle in self.loops.iter() {
+            let lines: Vec<String> = cycle.iter().map(|pc| (pc + 1).to_string()).collect();
+            let reached = if self.reachable[cycle[0]] {
+                "reached from the start"
+            } else if self.reaches_dynamic {
+                "maybe reached"
+            } else {
+                "never reached"
+            };
+            writeln!(
+                f,
+                "Loop of {} instructions, {}: lines {}",
+                cycle.len(),
+                reached,
+                lines.join(" -> ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::program::CODE;
+
+    #[test]
+    fn test_analysis() {
+        let program: Program = CODE.parse().unwrap();
+        let analysis = Analysis::new(&program);
+        assert_eq!(analysis.successors[2], Successor::Instruction(6));
+        assert_eq!(analysis.successors[8], Successor::End);
+        assert_eq!(analysis.unreachable(), vec![5, 8]);
+        assert_eq!(analysis.loops, vec![vec![1, 2, 6, 7, 3, 4]]);
+        assert_eq!(
+            analysis.to_string(),
+            "9 instructions, 7 reachable from the start
+Unreachable lines: 6, 9
+Loop of 6 instructions, reached from the start: lines 2 -> 3 -> 7 -> 8 -> 4 -> 5
+"
+        );
+    }
+
+    #[test]
+    fn test_several_loops() {
+        let program: Program = "jmp +3\njmp +0\njmp -3\nacc +1\njmp -1\nacc +2\njmp -6"
+            .parse()
+            .unwrap();
+        let analysis = Analysis::new(&program);
+        assert_eq!(analysis.loops, vec![vec![1], vec![3, 4]]);
+        assert_eq!(analysis.unreachable(), vec![1, 2, 5, 6]);
+        assert!(analysis
+            .to_string()
+            .contains("Unreachable lines: 2-3, 6-7\n"));
+        assert_eq!(analysis.successors[2], Successor::OutOfBounds);
+        assert_eq!(analysis.successors[6], Successor::Instruction(0));
+    }
+
+    #[test]
+    fn test_jump_overflow() {
+        let program: Program = format!("nop +0\njmp +{}\njmp {}", i64::MAX, i64::MIN)
+            .parse()
+            .unwrap();
+        let analysis = Analysis::new(&program);
+        assert_eq!(analysis.successors[1], Successor::OutOfBounds);
+        assert_eq!(analysis.successors[2], Successor::OutOfBounds);
+        assert_eq!(analysis.unreachable(), vec![2]);
+    }
+
+    #[test]
+    fn test_dynamic() {
+        let program = crate::opcodes::InstructionSet::extended()
+            .parse_program("acc +1\njnz +2\nhlt +0\nout +0")
+            .unwrap();
+        let analysis = Analysis::new(&program);
+        assert_eq!(analysis.successors[1], Successor::Dynamic);
+        assert_eq!(
+            analysis.to_string(),
+            "4 instructions, 2 reachable from the start
+Lines reached or not depending on conditional opcodes: 3-4
+Not followed past conditional opcodes at lines: 2
+"
+        );
+        assert!(analysis.unreachable().is_empty());
+        assert_eq!(analysis.unknown(), vec![2, 3]);
+        let dot = analysis.to_dot(&program);
+        assert!(dot.contains("    n1 [label=\"2: jnz +2\", shape=diamond];\n"));
+        assert!(dot.contains("    n2 [label=\"3: hlt +0\", style=dotted];\n"));
+        assert!(!dot.contains("n1 ->"));
+        assert!(dot.contains("    n2 -> end;\n    n3 -> end;\n"));
+
+        // The registered opcode is never reached, so the rest is surely unreachable
+        let program = crate::opcodes::InstructionSet::extended()
+            .parse_program("jmp +2\njnz +2\nacc +1\njmp -1\nout +0")
+            .unwrap();
+        let analysis = Analysis::new(&program);
+        assert!(!analysis.reaches_dynamic);
+        assert_eq!(analysis.unreachable(), vec![1, 4]);
+        assert!(analysis.unknown().is_empty());
+    }
+
+    #[test]
+    fn test_fixed_opcodes() {
+        // out and mul always go to the next instruction, so the loop is known, as when
+        // running it
+        let set = crate::opcodes::InstructionSet::extended();
+        let program = set.parse_program("out +0\nacc +1\njmp -2").unwrap();
+        let analysis = Analysis::new(&program);
+        assert_eq!(analysis.successors[0], Successor::Instruction(1));
+        assert!(!analysis.reaches_dynamic);
+        assert_eq!(analysis.loops, vec![vec![0, 1, 2]]);
+        assert!(analysis
+            .to_string()
+            .contains("Loop of 3 instructions, reached from the start: lines 1 -> 2 -> 3\n"));
+        assert_eq!(
+            crate::machine::Machine::new(&program).run(),
+            crate::machine::Halt::Loop { pc: 0 }
+        );
+
+        let program = set.parse_program("mul +2\nhlt +0\nacc +1").unwrap();
+        let analysis = Analysis::new(&program);
+        assert_eq!(
+            analysis.successors[..2],
+            [Successor::Instruction(1), Successor::End]
+        );
+        assert_eq!(analysis.unreachable(), vec![2]);
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let program: Program = "acc +1\njmp -1\njmp -5".parse().unwrap();
+        let dot = Analysis::new(&program).to_dot(&program);
+        assert_eq!(
+            dot,
+            "digraph program {
+    node [shape=box];
+    end [label=\"end\", shape=doublecircle];
+    out [label=\"out of bounds\", shape=octagon];
+    n0 [label=\"1: acc +1\", color=red];
+    n1 [label=\"2: jmp -1\", color=red];
+    n2 [label=\"3: jmp -5\", style=dashed];
+    n0 -> n1;
+    n1 -> n0;
+    n2 -> out;
+}
+"
+        );
+    }
+}
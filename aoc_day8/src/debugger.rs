@@ -0,0 +1,304 @@
+use crate::machine::{Halt, Machine};
+use crate::program::Program;
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "Commands:
+  step [n]     run the next n instructions, 1 by default
+  continue     run until a breakpoint, a watched change or the end
+  break <pc>   stop before running the instruction at pc, counting from 0
+  delete <pc>  remove the breakpoint at pc
+  watch acc    stop whenever the accumulator changes, or stop watching it
+  print        show the pc, the accumulator and the next instruction
+  quit         leave the debugger";
+
+/// Describes an instruction that has just run and the accumulator after it
+fn describe(machine: &Machine, pc: usize, before: i64) -> String {
+    let instruction = machine.instructions()[pc].to_string();
+    if machine.acc == before {
+        format!("{:>5}: {:<8} acc = {}", pc, instruction, machine.acc)
+    } else {
+        format!(
+            "{:>5}: {:<8} acc = {} -> {}",
+            pc, instruction, before, machine.acc
+        )
+    }
+}
+
+/// Runs the machine until it halts, writing each instruction as it runs
+pub fn trace<W: Write>(mut machine: Machine, out: &mut W) -> io::Result<(Halt, i64)> {
+    loop {
+        if let Some(halt) = machine.halted() {
+            writeln!(out, "Halted: {}", halt)?;
+            return Ok((halt, machine.acc));
+        }
+        let (pc, before) = (machine.pc, machine.acc);
+        machine.step();
+        writeln!(out, "{}", describe(&machine, pc, before))?;
+    }
+}
+
+/// An interactive debugger for the handheld console
+pub struct Debugger<'a> {
+    machine: Machine<'a>,
+    breakpoints: BTreeSet<usize>,
+    watch_acc: bool,
+}
+
+impl<'a> Debugger<'a> {
+    pub fn new(program: &'a Program) -> Debugger<'a> {
+        Debugger {
+            machine: Machine::new(program),
+            breakpoints: BTreeSet::new(),
+            watch_acc: false,
+        }
+    }
+
+    /// Changes the most steps to run a program with registered opcodes
+    pub fn with_step_limit(mut self, limit: usize) -> Debugger<'a> {
+        self.machine = self.machine.with_step_limit(limit);
+        self
+    }
+
+    /// Runs one instruction, adding its description to the output
+    ///
+    /// Returns whether the execution should stop, because the machine halted or the
+    /// watched accumulator changed.
+    fn step(&mut self, output: &mut Vec<String>) -> bool {
+        if let Some(halt) = self.machine.halted() {
+            output.push(format!("The program has halted: {}", halt));
+            return true;
+        }
+        let (pc, before) = (self.machine.pc, self.machine.acc);
+        let halt = self.machine.step();
+        output.push(describe(&self.machine, pc, before));
+        if let Some(halt) = halt {
+            output.push(format!("Halted: {}", halt));
+            return true;
+        }
+        if self.watch_acc && self.machine.acc != before {
+            output.push(format!(
+                "Accumulator changed from {} to {}",
+                before, self.machine.acc
+            ));
+            return true;
+        }
+        false
+    }
+
+    /// Runs a single command, returning what to show
+    pub fn execute(&mut self, command: &str) -> String {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        let mut output = Vec::new();
+        match words.as_slice() {
+            ["step"] | ["s"] => {
+                self.step(&mut output);
+            }
+            ["step", n] | ["s", n] => match n.parse::<usize>() {
+                Ok(n) => {
+                    for _ in 0..n {
+                        if self.step(&mut output) {
+                            break;
+                        }
+                    }
+                }
+                Err(_) => output.push(format!("Invalid number of steps '{}'", n)),
+            },
+            ["continue"] | ["c"] => loop {
+                if self.step(&mut output) {
+                    break;
+                }
+                if self.breakpoints.contains(&self.machine.pc) {
+                    output.push(format!("Breakpoint at pc {}", self.machine.pc));
+                    break;
+                }
+            },
+            ["break", pc] | ["b", pc] => match pc.parse::<usize>() {
+                Ok(pc) if pc < self.machine.instructions().len() => {
+                    self.breakpoints.insert(pc);
+                    output.push(format!("Breakpoint set at pc {}", pc));
+                }
+                _ => output.push(format!("Invalid pc '{}'", pc)),
+            },
+            ["delete", pc] | ["d", pc] => match pc.parse::<usize>() {
+                Ok(pc) if self.breakpoints.remove(&pc) => {
+                    output.push(format!("Breakpoint at pc {} deleted", pc))
+                }
+                _ => output.push(format!("No breakpoint at pc {}", pc)),
+            },
+            ["watch", "acc"] | ["w", "acc"] => {
+                self.watch_acc = !self.watch_acc;
+                output.push(if self.watch_acc {
+                    "Watching the accumulator".to_string()
+                } else {
+                    "Not watching the accumulator".to_string()
+                });
+            }
+            ["print"] | ["p"] => {
+                output.push(format!(
+                    "pc = {}, acc = {}",
+                    self.machine.pc, self.machine.acc
+                ));
+                match self.machine.next_instruction() {
+                    Some(instruction) => output.push(format!("Next: {}", instruction)),
+                    None => output.push(format!(
+                        "The program has halted: {}",
+                        self.machine.halted().unwrap()
+                    )),
+                }
+                if !self.breakpoints.is_empty() {
+                    let pcs: Vec<String> =
+                        self.breakpoints.iter().map(|pc| pc.to_string()).collect();
+                    output.push(format!("Breakpoints: {}", pcs.join(", ")));
+                }
+            }
+            ["help"] | ["h"] => output.push(HELP.to_string()),
+            [] => {}
+            _ => output.push(format!("Unknown command '{}', try 'help'", command.trim())),
+        }
+        output.join("\n")
+    }
+
+    /// Reads commands, one per line, until `quit` or the end of the input
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, out: &mut W) -> io::Result<()> {
+        write!(out, "> ")?;
+        out.flush()?;
+        for line in input.lines() {
+            let line = line?;
+            if matches!(line.trim(), "quit" | "q") {
+                return Ok(());
+            }
+            let output = self.execute(&line);
+            if !output.is_empty() {
+                writeln!(out, "{}", output)?;
+            }
+            write!(out, "> ")?;
+            out.flush()?;
+        }
+        writeln!(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::program::CODE;
+
+    #[test]
+    fn test_trace() {
+        let program: Program = CODE.parse().unwrap();
+        let mut out = Vec::new();
+        let result = trace(Machine::new(&program), &mut out).unwrap();
+        assert_eq!(result, (Halt::Loop { pc: 1 }, 5));
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 8);
+        assert_eq!(lines[0], "    0: nop +0   acc = 0");
+        assert_eq!(lines[1], "    1: acc +1   acc = 0 -> 1");
+        assert_eq!(lines[7], "Halted: infinite loop at pc 1");
+
+        let mut out = Vec::new();
+        let empty: Program = "".parse().unwrap();
+        let result = trace(Machine::new(&empty), &mut out).unwrap();
+        assert_eq!(result, (Halt::Terminated, 0));
+        assert_eq!(out, b"Halted: terminated\n");
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let program: Program = CODE.parse().unwrap();
+        let mut debugger = Debugger::new(&program);
+        assert_eq!(debugger.execute("break 3"), "Breakpoint set at pc 3");
+        assert_eq!(debugger.execute("break 30"), "Invalid pc '30'");
+        assert_eq!(
+            debugger.execute("continue"),
+            "    0: nop +0   acc = 0
+    1: acc +1   acc = 0 -> 1
+    2: jmp +4   acc = 1
+    6: acc +1   acc = 1 -> 2
+    7: jmp -4   acc = 2
+Breakpoint at pc 3"
+        );
+        assert_eq!(
+            debugger.execute("print"),
+            "pc = 3, acc = 2\nNext: acc +3\nBreakpoints: 3"
+        );
+        assert_eq!(debugger.execute("delete 3"), "Breakpoint at pc 3 deleted");
+        assert_eq!(
+            debugger.execute("c"),
+            "    3: acc +3   acc = 2 -> 5
+    4: jmp -3   acc = 5
+Halted: infinite loop at pc 1"
+        );
+        assert_eq!(
+            debugger.execute("step"),
+            "The program has halted: infinite loop at pc 1"
+        );
+    }
+
+    #[test]
+    fn test_watch_and_step() {
+        let program: Program = CODE.parse().unwrap();
+        let mut debugger = Debugger::new(&program);
+        assert_eq!(debugger.execute("watch acc"), "Watching the accumulator");
+        assert_eq!(
+            debugger.execute("continue"),
+            "    0: nop +0   acc = 0
+    1: acc +1   acc = 0 -> 1
+Accumulator changed from 0 to 1"
+        );
+        assert_eq!(debugger.execute("w acc"), "Not watching the accumulator");
+        assert_eq!(
+            debugger.execute("step 2"),
+            "    2: jmp +4   acc = 1\n    6: acc +1   acc = 1 -> 2"
+        );
+        assert_eq!(debugger.execute("step x"), "Invalid number of steps 'x'");
+        assert_eq!(
+            debugger.execute("jump 3"),
+            "Unknown command 'jump 3', try 'help'"
+        );
+    }
+
+    #[test]
+    fn test_continue_with_output() {
+        let program = crate::opcodes::InstructionSet::extended()
+            .parse_program("out +0\nacc +1\njmp -2")
+            .unwrap();
+        let mut debugger = Debugger::new(&program);
+        let output = debugger.execute("continue");
+        assert!(output.ends_with("Halted: infinite loop at pc 0"));
+        assert_eq!(output.lines().count(), 4);
+    }
+
+    #[test]
+    fn test_step_limit() {
+        let program = crate::opcodes::InstructionSet::extended()
+            .parse_program("acc +1\njnz -1")
+            .unwrap();
+        let mut out = Vec::new();
+        let result = trace(Machine::new(&program).with_step_limit(4), &mut out).unwrap();
+        assert_eq!(result, (Halt::StepLimit, 2));
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.lines().count(), 5);
+        assert!(out.ends_with("Halted: reached the step limit\n"));
+
+        let mut debugger = Debugger::new(&program).with_step_limit(3);
+        let output = debugger.execute("continue");
+        assert!(output.ends_with("    0: acc +1   acc = 1 -> 2\nHalted: reached the step limit"));
+    }
+
+    #[test]
+    fn test_run() {
+        let program: Program = CODE.parse().unwrap();
+        let mut debugger = Debugger::new(&program);
+        let mut out = Vec::new();
+        debugger
+            .run("step\nprint\nquit\nstep\n".as_bytes(), &mut out)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            ">     0: nop +0   acc = 0\n> pc = 1, acc = 0\nNext: acc +1\n> "
+        );
+    }
+}
@@ -0,0 +1,356 @@
+use crate::opcodes::Action;
+use crate::program::{Instruction, Program};
+use std::convert::TryFrom;
+use std::fmt;
+
+/// Why the machine stopped running
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Halt {
+    /// It tried to run the instruction right after the last one, or ran a `hlt`
+    Terminated,
+    /// It was about to run the instruction at `pc` again, which would repeat forever
+    ///
+    /// `pc` is the instruction that would repeat, not the last one run, which jumped back
+    /// to it. Where the loop closes tells the loop apart, while the last one run depends
+    /// only on where the program entered it.
+    Loop { pc: usize },
+    /// It jumped before the first instruction or past the end of the program
+    OutOfBounds,
+    /// The accumulator could not hold the result of an instruction
+    Overflow,
+    /// It ran the most steps allowed without halting otherwise
+    StepLimit,
+}
+
+impl fmt::Display for Halt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Halt::Terminated => write!(f, "terminated"),
+            Halt::Loop { pc } => write!(f, "infinite loop at pc {}", pc),
+            Halt::OutOfBounds => write!(f, "jumped out of the program"),
+            Halt::Overflow => write!(f, "accumulator overflow"),
+            Halt::StepLimit => write!(f, "reached the step limit"),
+        }
+    }
+}
+
+/// Most steps run by default for programs with registered opcodes
+///
+/// Their loops are only found once the pc and accumulator repeat, which may take longer than
+/// anyone would wait if the accumulator keeps changing. Other programs always halt before
+/// running more instructions than they have.
+pub const STEP_LIMIT: usize = 10_000_000;
+
+/// What tells a loop apart when the flow depends on the accumulator
+///
+/// The pc and the accumulator determine everything the machine does next, so it loops
+/// once it gets back to a pc and accumulator it has been at. That is found with Brent's
+/// cycle detection, saving the state at every power of two steps and comparing the
+/// following ones with it, so the memory used does not grow with the number of steps.
+#[derive(Debug, Clone)]
+struct DynamicState {
+    /// Number of conditional opcodes run so far
+    branches_run: usize,
+    /// Value of `branches_run` when each instruction last ran
+    last_run: Vec<usize>,
+    /// The pc and accumulator saved to compare with
+    saved: (usize, i64),
+    /// Steps between saving the state and saving it again
+    power: usize,
+    /// Steps since the state was saved
+    steps: usize,
+    /// Steps run so far
+    total_steps: usize,
+    /// Most steps to run before stopping
+    step_limit: usize,
+}
+
+/// The state of the handheld console running a program
+///
+/// Each instruction can only run once: running it again would mean an infinite loop. With
+/// conditional opcodes like `jz` the flow may depend on the accumulator, so it is only a
+/// loop if the instruction runs again without any of them running in between, or once the
+/// machine gets back to the same instruction and accumulator, which may be noticed a few
+/// steps after the first time.
+/// As that may take too long, they also stop after `STEP_LIMIT` steps.
+#[derive(Debug, Clone)]
+pub struct Machine<'a> {
+    program: &'a Program,
+    /// Position of the next instruction to run
+    pub pc: usize,
+    pub acc: i64,
+    /// Values written by output instructions, in order
+    pub output: Vec<i64>,
+    /// One bit per instruction, set once it has run
+    visited: Vec<u64>,
+    /// Only for programs with registered opcodes
+    dynamic: Option<DynamicState>,
+    halted: Option<Halt>,
+}
+
+impl<'a> Machine<'a> {
+    pub fn new(program: &'a Program) -> Machine<'a> {
+        Machine {
+            program,
+            pc: 0,
+            acc: 0,
+            output: Vec::new(),
+            visited: vec![0; program.len().div_ceil(64)],
+            dynamic: if program.is_dynamic() {
+                Some(DynamicState {
+                    branches_run: 0,
+                    last_run: vec![0; program.len()],
+                    saved: (0, 0),
+                    power: 1,
+                    steps: 0,
+                    total_steps: 0,
+                    step_limit: STEP_LIMIT,
+                })
+            } else {
+                None
+            },
+            halted: if program.len() == 0 {
+                Some(Halt::Terminated)
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Changes the most steps to run a program with registered opcodes
+    ///
+    /// Other programs are not limited, as they always halt before running more instructions
+    /// than they have.
+    pub fn with_step_limit(mut self, limit: usize) -> Machine<'a> {
+        if let Some(dynamic) = self.dynamic.as_mut() {
+            dynamic.step_limit = limit;
+        }
+        self
+    }
+
+    /// Whether the instruction at `pc` has run
+    pub fn is_visited(&self, pc: usize) -> bool {
+        self.visited[pc / 64] & (1 << (pc % 64)) != 0
+    }
+
+    /// Instructions of the program being run
+    pub fn instructions(&self) -> &'a [Instruction] {
+        &self.program.instructions
+    }
+
+    /// Why the machine stopped, or `None` if it is still running
+    pub fn halted(&self) -> Option<Halt> {
+        self.halted
+    }
+
+    /// The instruction that will run next, or `None` if the machine has halted
+    pub fn next_instruction(&self) -> Option<Instruction> {
+        match self.halted {
+            Some(_) => None,
+            None => Some(self.program.instructions[self.pc]),
+        }
+    }
+
+    /// Runs the instruction at `pc`, returning why the machine stopped if it did
+    ///
+    /// Once halted, the state does not change anymore. After a jump out of bounds, an
+    /// overflow or a `hlt`, `pc` is left at that instruction, and the accumulator is not
+    /// changed by an overflow.
+    pub fn step(&mut self) -> Option<Halt> {
+        if self.halted.is_some() {
+            return self.halted;
+        }
+        self.visited[self.pc / 64] |= 1 << (self.pc % 64);
+        let instruction = self.program.instructions[self.pc];
+        if let Some(dynamic) = self.dynamic.as_mut() {
+            dynamic.last_run[self.pc] = dynamic.branches_run;
+            if instruction.is_conditional() {
+                dynamic.branches_run += 1;
+            }
+        }
+        let jump = match instruction.execute(&mut self.acc) {
+            Action::Jump(jump) => jump,
+            Action::Output(value) => {
+                self.output.push(value);
+                1
+            }
+            Action::Halt => {
+                self.halted = Some(Halt::Terminated);
+                return self.halted;
+            }
+            Action::Overflow => {
+                self.halted = Some(Halt::Overflow);
+                return self.halted;
+            }
+        };
+
+        let len = self.program.len();
+        let next = (self.pc as i64)
+            .checked_add(jump)
+            .and_then(|next| usize::try_from(next).ok());
+        let next = match next {
+            Some(next) if next <= len => next,
+            _ => {
+                self.halted = Some(Halt::OutOfBounds);
+                return self.halted;
+            }
+        };
+        self.pc = next;
+        if next == len {
+            self.halted = Some(Halt::Terminated);
+        } else {
+            let visited = self.is_visited(next);
+            let repeated = match self.dynamic.as_mut() {
+                Some(dynamic) => {
+                    let state = (next, self.acc);
+                    let static_loop = visited && dynamic.last_run[next] == dynamic.branches_run;
+                    if static_loop || state == dynamic.saved {
+                        true
+                    } else {
+                        dynamic.total_steps += 1;
+                        if dynamic.total_steps >= dynamic.step_limit {
+                            self.halted = Some(Halt::StepLimit);
+                            return self.halted;
+                        }
+                        dynamic.steps += 1;
+                        if dynamic.steps == dynamic.power {
+                            dynamic.saved = state;
+                            dynamic.power *= 2;
+                            dynamic.steps = 0;
+                        }
+                        false
+                    }
+                }
+                None => visited,
+            };
+            if repeated {
+                self.halted = Some(Halt::Loop { pc: next });
+            }
+        }
+        self.halted
+    }
+
+    /// Runs the program until the machine halts
+    pub fn run(&mut self) -> Halt {
+        loop {
+            if let Some(halt) = self.step() {
+                return halt;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::opcodes::InstructionSet;
+
+    fn run(code: &str) -> (Halt, usize, i64) {
+        let program: Program = code.parse().unwrap();
+        let mut machine = Machine::new(&program);
+        let halt = machine.run();
+        (halt, machine.pc, machine.acc)
+    }
+
+    #[test]
+    fn test_step() {
+        let program: Program = "acc +2\njmp +2\nacc +5\nacc -1".parse().unwrap();
+        let mut machine = Machine::new(&program);
+        assert_eq!(machine.step(), None);
+        assert_eq!((machine.pc, machine.acc), (1, 2));
+        assert_eq!(machine.step(), None);
+        assert_eq!((machine.pc, machine.acc), (3, 2));
+        assert_eq!(machine.step(), Some(Halt::Terminated));
+        assert_eq!((machine.pc, machine.acc), (4, 1));
+        assert_eq!(machine.step(), Some(Halt::Terminated));
+        assert_eq!((machine.pc, machine.acc), (4, 1));
+    }
+
+    #[test]
+    fn test_halts() {
+        assert_eq!(run("nop +0\nacc +3\njmp -1"), (Halt::Loop { pc: 1 }, 1, 3));
+        assert_eq!(run("jmp +0"), (Halt::Loop { pc: 0 }, 0, 0));
+        assert_eq!(run("acc +1\njmp -2"), (Halt::OutOfBounds, 1, 1));
+        assert_eq!(run("acc +1\njmp +2"), (Halt::OutOfBounds, 1, 1));
+        assert_eq!(run(""), (Halt::Terminated, 0, 0));
+    }
+
+    #[test]
+    fn test_dynamic_loops() {
+        let set = InstructionSet::extended();
+        let program = set.parse_program("acc +3\nacc -1\njnz -1\nout +0").unwrap();
+        let mut machine = Machine::new(&program);
+        assert_eq!(machine.run(), Halt::Terminated);
+        assert_eq!(machine.output, vec![0]);
+
+        let program = set.parse_program("acc +1\nmul +1\njnz -1").unwrap();
+        let mut machine = Machine::new(&program);
+        assert_eq!(machine.run(), Halt::Loop { pc: 1 });
+        assert_eq!(machine.acc, 1);
+
+        // The accumulator flips between 1 and -1, so the loop takes two rounds
+        let program = set.parse_program("acc +1\nmul -1\njnz -1\nacc +5").unwrap();
+        let mut machine = Machine::new(&program);
+        assert_eq!(machine.run(), Halt::Loop { pc: 1 });
+        assert_eq!(machine.acc, -1);
+
+        let program = set
+            .parse_program("acc +1000000\nacc -1\njnz -1\nout +0")
+            .unwrap();
+        let mut machine = Machine::new(&program);
+        assert_eq!(machine.run(), Halt::Terminated);
+        assert_eq!(machine.output, vec![0]);
+
+        // The accumulator keeps changing, but the loop has no conditional opcode
+        let program = set.parse_program("mul +2\nacc +1\njmp -1").unwrap();
+        let mut machine = Machine::new(&program);
+        assert_eq!(machine.run(), Halt::Loop { pc: 1 });
+        assert_eq!(machine.acc, 1);
+
+        // Registered opcodes that always jump the same way do not hide a loop either
+        let program = set.parse_program("out +0\nacc +1\njmp -2").unwrap();
+        let mut machine = Machine::new(&program);
+        assert_eq!(machine.run(), Halt::Loop { pc: 0 });
+        assert_eq!(machine.acc, 1);
+        assert_eq!(machine.output, vec![0]);
+    }
+
+    #[test]
+    fn test_step_limit() {
+        // The accumulator never repeats, so it would run until it overflows
+        let set = InstructionSet::extended();
+        let program = set.parse_program("acc +1\njnz -1").unwrap();
+        let mut machine = Machine::new(&program);
+        assert_eq!(machine.run(), Halt::StepLimit);
+        assert_eq!(machine.acc, STEP_LIMIT as i64 / 2);
+
+        let mut machine = Machine::new(&program).with_step_limit(10);
+        assert_eq!(machine.run(), Halt::StepLimit);
+        assert_eq!((machine.pc, machine.acc), (0, 5));
+        assert_eq!(machine.step(), Some(Halt::StepLimit));
+
+        // Programs without registered opcodes are not limited
+        let program: Program = "acc +1\nacc +1\nacc +1".parse().unwrap();
+        let mut machine = Machine::new(&program).with_step_limit(1);
+        assert_eq!(machine.run(), Halt::Terminated);
+    }
+
+    #[test]
+    fn test_overflow() {
+        let max = i64::MAX;
+        let code = format!("nop +0\njmp +{}", max);
+        assert_eq!(run(&code), (Halt::OutOfBounds, 1, 0));
+        let code = format!("acc +{}\nacc +1\nacc +5", max);
+        assert_eq!(run(&code), (Halt::Overflow, 1, max));
+        let code = format!("acc {}\nacc -1", i64::MIN);
+        assert_eq!(run(&code), (Halt::Overflow, 1, i64::MIN));
+    }
+
+    #[test]
+    fn test_long_program() {
+        let n = 1_000_000;
+        let code = vec!["acc +1"; n].join("\n");
+        assert_eq!(run(&code), (Halt::Terminated, n, n as i64));
+    }
+}
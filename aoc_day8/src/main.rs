@@ -1,102 +1,179 @@
+mod cfg;
+mod debugger;
+mod machine;
+mod opcodes;
+mod program;
+mod repair;
+
+use cfg::Analysis;
+use debugger::{trace, Debugger};
+use machine::{Halt, Machine, STEP_LIMIT};
+use opcodes::InstructionSet;
+use program::Program;
+use repair::{all_repairs, minimal_repair, repair};
 use std::env;
 use std::fs;
+use std::io;
 
-/// Read file into vector of strings
-fn read_file(args: &[String]) -> Vec<String> {
+/// Read file into a string
+fn read_file(args: &[String]) -> String {
     let filename = &args[1];
     println!("Reading {}", &args[1]);
 
-    fs::read_to_string(filename)
-        .expect("Something went wrong reading the file")
-        .trim_end()
-        .split("\n")
-        .map(|s| s.to_string())
-        .collect()
+    fs::read_to_string(filename).expect("Something went wrong reading the file")
 }
 
-/// Parses a single instruction of code
+/// Executes a full sequence of orders
 ///
-/// The output is a tuple with the next step and the value to accumulate
-fn parse_order(order: &str) -> (isize, isize) {
-    let mut o = order.split_ascii_whitespace();
-
-    match o.next().unwrap() {
-        "acc" => return (1, o.next().unwrap().parse::<isize>().unwrap()),
-        "jmp" => return (o.next().unwrap().parse::<isize>().unwrap(), 0),
-        _ => return (1, 0),
-    }
+/// The output is why the execution stopped and the accumualtor at that time
+fn execute_code(code: &Program) -> (Halt, i64) {
+    let mut machine = Machine::new(code);
+    let halt = machine.run();
+    (halt, machine.acc)
 }
 
-/// Executes a full sequence of orders
-///
-/// The output is the last order executed and the accumualtor at that time
-fn execute_code(
-    current: usize,
-    code: &Vec<String>,
-    acc: isize,
-    mut done: Vec<usize>,
-) -> (isize, isize) {
-    let (step, val) = parse_order(&code[current]);
-    done.push(current);
-    if done.contains(&((current as isize + step) as usize)) {
-        return (current as isize, acc + val);
-    } else if (current as isize + step) as usize == code.len() {
-        return (-1, acc + val);
-    } else {
-        return execute_code((current as isize + step) as usize, code, acc + val, done);
-    }
+/// Takes `--max-steps <n>` out of the options, returning the number if it was there
+fn parse_step_limit(options: &mut Vec<&str>) -> Option<usize> {
+    let i = options.iter().position(|o| *o == "--max-steps")?;
+    let limit = options
+        .get(i + 1)
+        .expect("Missing the maximum number of steps")
+        .parse::<usize>()
+        .expect("The maximum number of steps must be a non-negative integer");
+    options.drain(i..i + 2);
+    Some(limit)
 }
 
-/// Repairs the code by changing sequentially jmp and nop with each other
-///
-/// After changing that, it runs the code and checks if the current output is -1
-fn repair_code(code: &Vec<String>) -> (isize, isize) {
-    for (i, order) in code.iter().enumerate() {
-        let mut new_code = code.clone();
-        if order.contains("jmp") {
-            new_code[i] = order.replace("jmp", "nop");
-        } else if order.contains("nop") {
-            new_code[i] = order.replace("nop", "jmp");
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let mut options: Vec<&str> = args[2..].iter().map(|a| a.as_str()).collect();
+    let instruction_set = match options.iter().position(|o| *o == "--extended") {
+        Some(i) => {
+            options.remove(i);
+            InstructionSet::extended()
+        }
+        None => InstructionSet::default(),
+    };
+    let step_limit = parse_step_limit(&mut options);
+    if step_limit.is_some() && !matches!(options.as_slice(), [] | ["--trace"] | ["--debug"]) {
+        panic!("--max-steps can only be used to run, trace or debug the program");
+    }
+    let step_limit = step_limit.unwrap_or(STEP_LIMIT);
+    let code = match instruction_set.parse_program(&read_file(&args)) {
+        Ok(code) => code,
+        Err(e) => {
+            println!("Invalid program at {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    match options.as_slice() {
+        [] => {}
+        ["--trace"] => {
+            let machine = Machine::new(&code).with_step_limit(step_limit);
+            trace(machine, &mut io::stdout()).expect("Something went wrong writing the trace");
         }
-        let (status, acc) = execute_code(0, &new_code, 0, Vec::new());
-        if status == -1 {
-            return (status, acc);
+        ["--debug"] => {
+            Debugger::new(&code)
+                .with_step_limit(step_limit)
+                .run(io::stdin().lock(), &mut io::stdout())
+                .expect("Something went wrong running the debugger");
+            return;
         }
+        ["--all-repairs"] => {
+            for repaired in all_repairs(&code).iter() {
+                println!(
+                    "Line {}: {} -> {}, accumulator {}",
+                    repaired.pc + 1,
+                    repaired.original,
+                    repaired.patched,
+                    repaired.acc
+                );
+            }
+            return;
+        }
+        ["--minimal-repair"] => {
+            let minimal = match minimal_repair(&code) {
+                Some(minimal) => minimal,
+                None => {
+                    println!("The code could not be repaired");
+                    return;
+                }
+            };
+            println!(
+                "The code terminates after changing {} instructions",
+                minimal.repairs.len()
+            );
+            for repaired in minimal.repairs.iter() {
+                println!(
+                    "Line {}: {} -> {}",
+                    repaired.pc + 1,
+                    repaired.original,
+                    repaired.patched
+                );
+            }
+            println!("The accumulator after rapairing code is {}", minimal.acc);
+            return;
+        }
+        ["--analyse"] => {
+            print!("{}", Analysis::new(&code));
+            return;
+        }
+        ["--dot", filename] => {
+            fs::write(filename, Analysis::new(&code).to_dot(&code))
+                .expect("Something went wrong writing the file");
+            println!("Exported control flow graph to {}", filename);
+            return;
+        }
+        _ => panic!(
+            "Usage: aoc_day8 <file> [--extended] [--max-steps <n>] [--trace | --debug | --all-repairs | \
+             --minimal-repair | --analyse | --dot <file>]"
+        ),
     }
-    return (-2, 0);
-}
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    let code = read_file(&args);
+    // Programs using the extended opcodes are just run, as they are not boot code
+    if code.is_dynamic() {
+        let mut machine = Machine::new(&code).with_step_limit(step_limit);
+        println!("The program halted: {}", machine.run());
+        println!("The accumulator was {}", machine.acc);
+        let output: Vec<String> = machine.output.iter().map(|v| v.to_string()).collect();
+        println!("Output: {}", output.join(", "));
+        return;
+    }
 
-    // Value in accumulator before infinite loop
-    let (_, accumulator) = execute_code(0, &code, 0, Vec::new());
-    println!("The accumulator before infinite loop was {}", accumulator);
+    // Value in accumulator when the program halts, usually before an infinite loop
+    let (halt, accumulator) = execute_code(&code);
+    println!("The program halted: {}", halt);
+    println!("The accumulator was {}", accumulator);
+    if halt == Halt::Terminated {
+        return;
+    }
 
     // Solve the problem and get the accumulator
-    let (_, accumulator) = repair_code(&code);
-    println!("The accumulator after rapairing code is {}", accumulator);
+    match repair(&code) {
+        Some(repaired) => {
+            println!(
+                "Patched line {}: {} -> {}",
+                repaired.pc + 1,
+                repaired.original,
+                repaired.patched
+            );
+            println!("The accumulator after rapairing code is {}", repaired.acc);
+        }
+        None => println!("The code could not be repaired"),
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
     use super::*;
-
-    const CODE: &str = "nop +0
-acc +1
-jmp +4
-acc +3
-jmp -3
-acc -99
-acc +1
-jmp -4
-acc +6";
+    use aoc_common::Rng;
+    use program::{Instruction, CODE};
 
     #[test]
-    fn test_parse_order() {
-        let code: Vec<String> = CODE.split("\n").map(|s| s.to_string()).collect();
+    fn test_effect() {
+        let code: Program = CODE.parse().unwrap();
         let expected = [
             (1, 0),
             (1, 1),
@@ -109,8 +186,8 @@ acc +6";
             (1, 6),
         ];
 
-        for (c, exp) in code.iter().zip(expected.iter()) {
-            let actual = parse_order(c);
+        for (c, exp) in code.instructions.iter().zip(expected.iter()) {
+            let actual = c.effect().unwrap();
             assert_eq!(actual.0, exp.0);
             assert_eq!(actual.1, exp.1);
         }
@@ -118,17 +195,150 @@ acc +6";
 
     #[test]
     fn test_execute_code() {
-        let code: Vec<String> = CODE.split("\n").map(|s| s.to_string()).collect();
-        let (current, accumulator) = execute_code(0, &code, 0, Vec::new());
-        assert_eq!(current, 4);
-        assert_eq!(accumulator, 5)
+        let code: Program = CODE.parse().unwrap();
+        let (halt, accumulator) = execute_code(&code);
+        assert_eq!(halt, Halt::Loop { pc: 1 });
+        assert_eq!(accumulator, 5);
+
+        // The last instruction run before the loop repeats is the jmp at 4
+        let mut machine = Machine::new(&code);
+        let mut last = machine.pc;
+        while machine.halted().is_none() {
+            last = machine.pc;
+            machine.step();
+        }
+        assert_eq!(last, 4);
+    }
+
+    /// Repairs the code by changing sequentially jmp and nop with each other
+    ///
+    /// After changing that, it runs the code and checks if it terminates, returning the
+    /// accumulator at the end, or `None` if no change makes it terminate.
+    fn repair_code(code: &Program) -> Option<i64> {
+        for (i, order) in code.instructions.iter().enumerate() {
+            let mut new_code = code.clone();
+            match order.flipped() {
+                Some(flipped) => new_code.instructions[i] = flipped,
+                None => continue,
+            }
+            if let (Halt::Terminated, acc) = execute_code(&new_code) {
+                return Some(acc);
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn test_parse_step_limit() {
+        let mut options = vec!["--max-steps", "10", "--trace"];
+        assert_eq!(parse_step_limit(&mut options), Some(10));
+        assert_eq!(options, vec!["--trace"]);
+        assert_eq!(parse_step_limit(&mut options), None);
+        assert_eq!(options, vec!["--trace"]);
     }
 
     #[test]
     fn test_repair_code() {
-        let code: Vec<String> = CODE.split("\n").map(|s| s.to_string()).collect();
-        let (current, accumulator) = repair_code(&code);
-        assert_eq!(current, -1);
-        assert_eq!(accumulator, 8)
+        let code: Program = CODE.parse().unwrap();
+        assert_eq!(repair_code(&code), Some(8));
+        assert_eq!(repair(&code).map(|r| r.acc), Some(8));
+        let code: Program = "jmp +0\njmp -1".parse().unwrap();
+        assert_eq!(repair_code(&code), None);
+        assert_eq!(repair(&code), None);
+    }
+
+    /// Generate a program that visits every instruction once and then corrupt it
+    ///
+    /// The last `jmp` to run whose next instruction was already run is changed into a
+    /// `nop`, so the program loops near the end and flipping it back repairs it.
+    fn generate_program(ninstructions: usize, seed: u64) -> Program {
+        let mut rng = Rng::new(seed);
+        let mut next = |m: usize| rng.below(m);
+        // Order in which the instructions run, starting with the first one
+        let mut order: Vec<usize> = (0..ninstructions).collect();
+        for i in (2..ninstructions).rev() {
+            order.swap(i, 1 + next(i));
+        }
+        order.push(ninstructions);
+
+        let mut position = vec![0; ninstructions + 1];
+        let mut instructions = vec![Instruction::Nop(0); ninstructions];
+        for (k, pc) in order.iter().enumerate().take(ninstructions) {
+            position[*pc] = k;
+            let jump = order[k + 1] as i64 - *pc as i64;
+            instructions[*pc] = match (jump, next(2)) {
+                (1, 0) => Instruction::Acc(next(100) as i64 - 50),
+                (1, _) => Instruction::Nop(next(100) as i64 - 50),
+                _ => Instruction::Jmp(jump),
+            };
+        }
+        position[ninstructions] = ninstructions;
+
+        let corrupt = order[..ninstructions]
+            .iter()
+            .rev()
+            .find(|pc| position[*pc + 1] < position[**pc])
+            .copied()
+            .expect("No instruction to corrupt");
+        instructions[corrupt] = instructions[corrupt].flipped().unwrap();
+        Program { instructions }
+    }
+
+    #[test]
+    fn test_repair_generated() {
+        for seed in 0..20 {
+            let program = generate_program(300, seed);
+            assert!(matches!(execute_code(&program), (Halt::Loop { .. }, _)));
+            let repaired = repair(&program).unwrap();
+            let mut patched = program.clone();
+            patched.instructions[repaired.pc] = repaired.patched;
+            assert_eq!(execute_code(&patched), (Halt::Terminated, repaired.acc));
+            assert!(repair_code(&program).is_some());
+
+            // Every flip that fixes the program, found one by one
+            let expected: Vec<(usize, i64)> = (0..program.len())
+                .filter_map(|pc| {
+                    let mut patched = program.clone();
+                    patched.instructions[pc] = patched.instructions[pc].flipped()?;
+                    match execute_code(&patched) {
+                        (Halt::Terminated, acc) => Some((pc, acc)),
+                        _ => None,
+                    }
+                })
+                .collect();
+            let mut found: Vec<(usize, i64)> = all_repairs(&program)
+                .iter()
+                .map(|r| (r.pc, r.acc))
+                .collect();
+            found.sort_unstable();
+            assert_eq!(found, expected);
+            assert_eq!(minimal_repair(&program).unwrap().repairs.len(), 1);
+        }
+    }
+
+    /// Times the brute force repair against the linear one, then the linear one alone on
+    /// a million instructions
+    #[test]
+    #[ignore]
+    fn bench_repair() {
+        let program = generate_program(20_000, 7);
+        let start = std::time::Instant::now();
+        let brute = repair_code(&program);
+        let quadratic = start.elapsed();
+
+        let start = std::time::Instant::now();
+        let linear = repair(&program);
+        let reachability = start.elapsed();
+        assert!(brute.is_some() && linear.is_some());
+
+        let program = generate_program(1_000_000, 7);
+        let start = std::time::Instant::now();
+        let large = repair(&program);
+        let million = start.elapsed();
+        assert!(large.is_some());
+        println!(
+            "20k instructions: repair_code {:?}, repair {:?}; 1M instructions: repair {:?}",
+            quadratic, reachability, million
+        );
     }
 }
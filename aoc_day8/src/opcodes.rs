@@ -0,0 +1,273 @@
+use crate::program::{Instruction, ParseInstructionError, ParseProgramError, Program};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+/// What the machine does after running an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Moves relative to the current instruction, 1 being the next one
+    Jump(i64),
+    /// Outputs the value and moves to the next instruction
+    Output(i64),
+    /// Stops the program, as if it had run the last instruction
+    Halt,
+    /// Stops the program, as the accumulator could not hold the result
+    Overflow,
+}
+
+/// An operation that is not one of the three the console started with
+///
+/// It runs with its argument and the accumulator, which it can change, and says what to
+/// do next.
+#[derive(Clone, Copy)]
+pub struct Opcode {
+    pub name: &'static str,
+    pub run: fn(i64, &mut i64) -> Action,
+    /// Whether where it jumps depends on the accumulator, like `jz`
+    ///
+    /// Otherwise it must always jump by the same amount for the same argument, as the
+    /// machine relies on it to find loops.
+    pub conditional: bool,
+}
+
+impl fmt::Debug for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Opcode({})", self.name)
+    }
+}
+
+/// Opcodes are told apart by name, as names are unique within an instruction set
+impl PartialEq for Opcode {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for Opcode {}
+
+/// Error produced when registering an opcode whose name is already in use
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegisterError {
+    pub name: String,
+}
+
+impl fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "opcode '{}' is already registered", self.name)
+    }
+}
+
+impl Error for RegisterError {}
+
+/// How to build an instruction with a given name
+#[derive(Clone, Copy)]
+enum Definition {
+    Builtin(fn(i64) -> Instruction),
+    Custom(Opcode),
+}
+
+/// The opcodes a program can use, by name
+///
+/// The default set has `acc`, `jmp` and `nop`. More can be added with `register`.
+#[derive(Clone)]
+pub struct InstructionSet {
+    opcodes: HashMap<&'static str, Definition>,
+}
+
+impl Default for InstructionSet {
+    fn default() -> Self {
+        let mut opcodes: HashMap<&'static str, Definition> = HashMap::new();
+        opcodes.insert("acc", Definition::Builtin(Instruction::Acc));
+        opcodes.insert("jmp", Definition::Builtin(Instruction::Jmp));
+        opcodes.insert("nop", Definition::Builtin(Instruction::Nop));
+        InstructionSet { opcodes }
+    }
+}
+
+impl InstructionSet {
+    /// The default set plus:
+    ///
+    /// - `jz` and `jnz`, which jump if the accumulator is zero or not zero
+    /// - `mul`, which multiplies the accumulator by the argument
+    /// - `hlt`, which stops the program
+    /// - `out`, which outputs the accumulator plus the argument
+    ///
+    /// `mul` and `out` stop the program with an overflow if the result does not fit.
+    pub fn extended() -> InstructionSet {
+        let mut set = InstructionSet::default();
+        let opcodes = [
+            Opcode {
+                name: "jz",
+                run: |arg, acc| Action::Jump(if *acc == 0 { arg } else { 1 }),
+                conditional: true,
+            },
+            Opcode {
+                name: "jnz",
+                run: |arg, acc| Action::Jump(if *acc != 0 { arg } else { 1 }),
+                conditional: true,
+            },
+            Opcode {
+                name: "mul",
+                run: |arg, acc| match acc.checked_mul(arg) {
+                    Some(result) => {
+                        *acc = result;
+                        Action::Jump(1)
+                    }
+                    None => Action::Overflow,
+                },
+                conditional: false,
+            },
+            Opcode {
+                name: "hlt",
+                run: |_, _| Action::Halt,
+                conditional: false,
+            },
+            Opcode {
+                name: "out",
+                run: |arg, acc| match acc.checked_add(arg) {
+                    Some(value) => Action::Output(value),
+                    None => Action::Overflow,
+                },
+                conditional: false,
+            },
+        ];
+        for opcode in opcodes.iter() {
+            set.register(*opcode).unwrap();
+        }
+        set
+    }
+
+    /// Adds an opcode, failing if there is already one with the same name
+    pub fn register(&mut self, opcode: Opcode) -> Result<(), RegisterError> {
+        if self.opcodes.contains_key(opcode.name) {
+            return Err(RegisterError {
+                name: opcode.name.to_string(),
+            });
+        }
+        self.opcodes.insert(opcode.name, Definition::Custom(opcode));
+        Ok(())
+    }
+
+    /// Parses a single instruction like `acc +1`
+    pub fn parse(&self, s: &str) -> Result<Instruction, ParseInstructionError> {
+        let mut words = s.split_ascii_whitespace();
+        let name = words.next().ok_or(ParseInstructionError::MissingOpcode)?;
+        let arg = words.next().ok_or(ParseInstructionError::MissingArgument)?;
+        let arg: i64 = arg
+            .parse()
+            .map_err(|_| ParseInstructionError::InvalidArgument(arg.to_string()))?;
+        if let Some(rest) = words.next() {
+            return Err(ParseInstructionError::TrailingInput(rest.to_string()));
+        }
+        match self.opcodes.get(name) {
+            Some(Definition::Builtin(build)) => Ok(build(arg)),
+            Some(Definition::Custom(opcode)) => Ok(Instruction::Custom(*opcode, arg)),
+            None => Err(ParseInstructionError::UnknownOpcode(name.to_string())),
+        }
+    }
+
+    /// Parses a program, one instruction per line, ignoring blank lines at the end
+    pub fn parse_program(&self, s: &str) -> Result<Program, ParseProgramError> {
+        let instructions = s
+            .trim_end()
+            .lines()
+            .enumerate()
+            .map(|(i, line)| {
+                self.parse(line)
+                    .map_err(|error| ParseProgramError { line: i + 1, error })
+            })
+            .collect::<Result<Vec<Instruction>, ParseProgramError>>()?;
+        Ok(Program { instructions })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::machine::{Halt, Machine};
+
+    #[test]
+    fn test_default_set() {
+        let set = InstructionSet::default();
+        assert_eq!(set.parse("jmp -2"), Ok(Instruction::Jmp(-2)));
+        assert_eq!(
+            set.parse("mul +2"),
+            Err(ParseInstructionError::UnknownOpcode("mul".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_register() {
+        let mut set = InstructionSet::default();
+        let double = Opcode {
+            name: "dbl",
+            run: |_, acc| {
+                *acc *= 2;
+                Action::Jump(1)
+            },
+            conditional: false,
+        };
+        assert_eq!(set.register(double), Ok(()));
+        assert_eq!(
+            set.register(double).unwrap_err().to_string(),
+            "opcode 'dbl' is already registered"
+        );
+        let nop = Opcode {
+            name: "nop",
+            run: |_, _| Action::Halt,
+            conditional: false,
+        };
+        assert!(set.register(nop).is_err());
+
+        let instruction = set.parse("dbl +0").unwrap();
+        assert_eq!(instruction, Instruction::Custom(double, 0));
+        assert_eq!(instruction.to_string(), "dbl +0");
+
+        let program = set.parse_program("acc +3\ndbl +0\ndbl +0").unwrap();
+        let mut machine = Machine::new(&program);
+        assert_eq!(machine.run(), Halt::Terminated);
+        assert_eq!(machine.acc, 12);
+    }
+
+    #[test]
+    fn test_extended_set() {
+        // Counts down from 3, outputting each value, then stops before the last line
+        let program = InstructionSet::extended()
+            .parse_program(
+                "acc +3
+out +0
+acc -1
+jnz -2
+mul +5
+jz +2
+acc +99
+hlt +0
+acc +1000",
+            )
+            .unwrap();
+        let mut machine = Machine::new(&program);
+        assert_eq!(machine.run(), Halt::Terminated);
+        assert_eq!(machine.acc, 0);
+        assert_eq!(machine.pc, 7);
+        assert_eq!(machine.output, vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn test_extended_overflow() {
+        let set = InstructionSet::extended();
+        let program = set.parse_program("acc +3\nmul +2\njnz -1").unwrap();
+        let mut machine = Machine::new(&program);
+        assert_eq!(machine.run(), Halt::Overflow);
+        assert_eq!(machine.pc, 1);
+        assert_eq!(machine.acc, 3 << 61);
+
+        let program = set
+            .parse_program(&format!("acc +{}\nout +1", i64::MAX))
+            .unwrap();
+        let mut machine = Machine::new(&program);
+        assert_eq!(machine.run(), Halt::Overflow);
+        assert!(machine.output.is_empty());
+    }
+}
@@ -0,0 +1,245 @@
+use crate::opcodes::{Action, InstructionSet, Opcode};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// A single instruction of the handheld console
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// Adds the argument to the accumulator
+    Acc(i64),
+    /// Jumps relative to the current instruction
+    Jmp(i64),
+    /// Does nothing, ignoring the argument
+    Nop(i64),
+    /// An opcode registered in the instruction set, with its argument
+    Custom(Opcode, i64),
+}
+
+impl Instruction {
+    /// The jump to the next instruction and the value to accumulate
+    ///
+    /// `None` for registered opcodes, as what they do depends on the accumulator.
+    pub fn effect(&self) -> Option<(i64, i64)> {
+        match *self {
+            Instruction::Acc(arg) => Some((1, arg)),
+            Instruction::Jmp(arg) => Some((arg, 0)),
+            Instruction::Nop(_) => Some((1, 0)),
+            Instruction::Custom(..) => None,
+        }
+    }
+
+    /// Runs the instruction, updating the accumulator and saying what to do next
+    pub fn execute(&self, acc: &mut i64) -> Action {
+        match *self {
+            Instruction::Custom(opcode, arg) => (opcode.run)(arg, acc),
+            _ => {
+                let (jump, value) = self.effect().unwrap();
+                match acc.checked_add(value) {
+                    Some(result) => {
+                        *acc = result;
+                        Action::Jump(jump)
+                    }
+                    None => Action::Overflow,
+                }
+            }
+        }
+    }
+
+    /// Whether where it jumps depends on the accumulator, as with `jz`
+    pub fn is_conditional(&self) -> bool {
+        matches!(self, Instruction::Custom(opcode, _) if opcode.conditional)
+    }
+
+    /// What it does next when that does not depend on the accumulator
+    ///
+    /// Registered opcodes that are not conditional always do the same for the same
+    /// argument, so they are run once with the accumulator at 0 to find out. `None` for
+    /// conditional ones.
+    pub fn fixed_action(&self) -> Option<Action> {
+        match *self {
+            Instruction::Custom(opcode, arg) if !opcode.conditional => {
+                Some((opcode.run)(arg, &mut 0))
+            }
+            _ => self.effect().map(|(jump, _)| Action::Jump(jump)),
+        }
+    }
+
+    /// Swaps `jmp` and `nop`, keeping the argument, or `None` for any other instruction
+    pub fn flipped(&self) -> Option<Instruction> {
+        match *self {
+            Instruction::Jmp(arg) => Some(Instruction::Nop(arg)),
+            Instruction::Nop(arg) => Some(Instruction::Jmp(arg)),
+            _ => None,
+        }
+    }
+}
+
+/// Error produced when an instruction cannot be parsed
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseInstructionError {
+    MissingOpcode,
+    UnknownOpcode(String),
+    MissingArgument,
+    InvalidArgument(String),
+    /// Anything after the argument
+    TrailingInput(String),
+}
+
+impl fmt::Display for ParseInstructionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseInstructionError::MissingOpcode => write!(f, "missing opcode"),
+            ParseInstructionError::UnknownOpcode(op) => write!(f, "unknown opcode '{}'", op),
+            ParseInstructionError::MissingArgument => write!(f, "missing argument"),
+            ParseInstructionError::InvalidArgument(arg) => write!(f, "invalid argument '{}'", arg),
+            ParseInstructionError::TrailingInput(rest) => {
+                write!(f, "unexpected '{}' after the argument", rest)
+            }
+        }
+    }
+}
+
+impl Error for ParseInstructionError {}
+
+impl FromStr for Instruction {
+    type Err = ParseInstructionError;
+
+    /// Parses an instruction of the default set
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        InstructionSet::default().parse(s)
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::Acc(arg) => write!(f, "acc {:+}", arg),
+            Instruction::Jmp(arg) => write!(f, "jmp {:+}", arg),
+            Instruction::Nop(arg) => write!(f, "nop {:+}", arg),
+            Instruction::Custom(opcode, arg) => write!(f, "{} {:+}", opcode.name, arg),
+        }
+    }
+}
+
+/// Error produced when a program cannot be parsed, with the line where it happened
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseProgramError {
+    pub line: usize,
+    pub error: ParseInstructionError,
+}
+
+impl fmt::Display for ParseProgramError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.error)
+    }
+}
+
+impl Error for ParseProgramError {}
+
+/// The boot code of the handheld console, one instruction per line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub instructions: Vec<Instruction>,
+}
+
+impl Program {
+    /// Number of instructions in the program
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    /// Whether any instruction is a registered opcode, so the flow is only known when running
+    pub fn is_dynamic(&self) -> bool {
+        self.instructions
+            .iter()
+            .any(|instruction| instruction.effect().is_none())
+    }
+}
+
+impl FromStr for Program {
+    type Err = ParseProgramError;
+
+    /// Parses a program of the default instruction set, ignoring blank lines at the end
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        InstructionSet::default().parse_program(s)
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for instruction in self.instructions.iter() {
+            writeln!(f, "{}", instruction)?;
+        }
+        Ok(())
+    }
+}
+
+/// The example boot code from the puzzle, which loops, for the tests of every module
+#[cfg(test)]
+pub const CODE: &str = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_parse_instruction() {
+        assert_eq!("acc +1".parse(), Ok(Instruction::Acc(1)));
+        assert_eq!("jmp -3".parse(), Ok(Instruction::Jmp(-3)));
+        assert_eq!("nop +0".parse(), Ok(Instruction::Nop(0)));
+        assert_eq!(" jmp  4\r".parse(), Ok(Instruction::Jmp(4)));
+    }
+
+    #[test]
+    fn test_parse_instruction_errors() {
+        let parse = |s: &str| s.parse::<Instruction>().unwrap_err();
+        assert_eq!(parse(""), ParseInstructionError::MissingOpcode);
+        assert_eq!(parse("acc"), ParseInstructionError::MissingArgument);
+        assert_eq!(
+            parse("mul +2"),
+            ParseInstructionError::UnknownOpcode("mul".to_string())
+        );
+        assert_eq!(
+            parse("jmp x"),
+            ParseInstructionError::InvalidArgument("x".to_string())
+        );
+        assert_eq!(
+            parse("jmp +1 +2"),
+            ParseInstructionError::TrailingInput("+2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_program() {
+        let program: Program = "nop +0\nacc +1\njmp -2\n\n".parse().unwrap();
+        assert_eq!(
+            program.instructions,
+            vec![
+                Instruction::Nop(0),
+                Instruction::Acc(1),
+                Instruction::Jmp(-2)
+            ]
+        );
+        assert_eq!(program.to_string(), "nop +0\nacc +1\njmp -2\n");
+
+        let error = "nop +0\nacc one\n".parse::<Program>().unwrap_err();
+        assert_eq!(error.to_string(), "line 2: invalid argument 'one'");
+    }
+
+    #[test]
+    fn test_flipped() {
+        assert_eq!(Instruction::Jmp(-3).flipped(), Some(Instruction::Nop(-3)));
+        assert_eq!(Instruction::Nop(2).flipped(), Some(Instruction::Jmp(2)));
+        assert_eq!(Instruction::Acc(2).flipped(), None);
+    }
+}
@@ -0,0 +1,494 @@
+use crate::machine::{Halt, Machine};
+use crate::program::{Instruction, Program};
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+
+/// A change of a single `jmp` into `nop`, or the other way around, that fixes a program
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Repair {
+    /// Position of the changed instruction, counting from 0
+    pub pc: usize,
+    pub original: Instruction,
+    pub patched: Instruction,
+    /// Value of the accumulator when the repaired program terminates
+    pub acc: i64,
+}
+
+/// Position of the instruction run after the one at `pc`, if it is within the program
+///
+/// The position right after the last instruction, where the program terminates, counts as
+/// within the program. Registered opcodes have no known target.
+fn target(program: &Program, pc: usize, instruction: Instruction) -> Option<usize> {
+    let (jump, _) = instruction.effect()?;
+    (pc as i64)
+        .checked_add(jump)
+        .and_then(|next| usize::try_from(next).ok())
+        .filter(|next| *next <= program.len())
+}
+
+/// The instructions that jump to each position, including the end
+fn sources(program: &Program) -> Vec<Vec<usize>> {
+    let mut sources: Vec<Vec<usize>> = vec![Vec::new(); program.len() + 1];
+    for (pc, instruction) in program.instructions.iter().enumerate() {
+        if let Some(next) = target(program, pc, *instruction) {
+            sources[next].push(pc);
+        }
+    }
+    sources
+}
+
+/// What running from an instruction to the end adds to the accumulator
+///
+/// These fit in an `i128` for any program that fits in memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SumsToEnd {
+    /// Added by the time the program terminates
+    total: i128,
+    /// Lowest and highest added at any point, including 0 before running anything, so an
+    /// accumulator does not overflow on the way exactly when it fits plus both of them
+    lowest: i128,
+    highest: i128,
+}
+
+/// What running from each position to the end adds to the accumulator, including the end
+///
+/// Works backwards from the end, following the instructions that jump to each one. Those
+/// that never lead to the end are `None`.
+fn sums_to_end(program: &Program) -> Vec<Option<SumsToEnd>> {
+    let len = program.len();
+    let sources = sources(program);
+    let mut sums: Vec<Option<SumsToEnd>> = vec![None; len + 1];
+    sums[len] = Some(SumsToEnd {
+        total: 0,
+        lowest: 0,
+        highest: 0,
+    });
+    let mut pending = vec![len];
+    while let Some(pc) = pending.pop() {
+        let rest = sums[pc].unwrap();
+        for &source in sources[pc].iter() {
+            if sums[source].is_none() {
+                let (_, value) = program.instructions[source].effect().unwrap();
+                let value = value as i128;
+                sums[source] = Some(SumsToEnd {
+                    total: value + rest.total,
+                    lowest: (value + rest.lowest).min(0),
+                    highest: (value + rest.highest).max(0),
+                });
+                pending.push(source);
+            }
+        }
+    }
+    sums
+}
+
+/// Finds every `jmp` or `nop` that can be flipped so the program terminates
+///
+/// The original program is run first, and there are no repairs if it terminates, even if
+/// only through a registered opcode like `hlt`. Otherwise none of the instructions it
+/// runs lead to the end, so flipping one of them fixes the program if it then jumps to an
+/// instruction that does. Flipping any other instruction makes no difference. The repairs
+/// are in the order the original program runs them. Repairs whose accumulator would
+/// overflow on the way to the end are left out.
+///
+/// With registered opcodes like `jnz` the original program may run an instruction more
+/// than once. The repaired program runs the same way until it first gets to the flipped
+/// instruction, so only that first time is a repair.
+///
+/// If the original program stops with an overflow, the instructions it runs may lead to
+/// the end after all, so the way from a flip to the end can go back through the flipped
+/// instruction. Each repair is then checked by running the repaired program.
+pub fn all_repairs(program: &Program) -> Vec<Repair> {
+    let mut repairs = Vec::new();
+    let mut machine = Machine::new(program);
+    let halt = machine.clone().run();
+    if halt == Halt::Terminated {
+        return repairs;
+    }
+
+    let sums = sums_to_end(program);
+    loop {
+        let pc = machine.pc;
+        let original = program.instructions[pc];
+        let patched = original.flipped().filter(|_| !machine.is_visited(pc));
+        let next = patched.and_then(|patched| target(program, pc, patched));
+        let rest = next.and_then(|next| sums[next]);
+        if let (Some(patched), Some(rest)) = (patched, rest) {
+            let start = machine.acc as i128;
+            let fits = |added: i128| i64::try_from(start + added).is_ok();
+            if fits(rest.lowest) && fits(rest.highest) {
+                // The total is between the lowest and the highest, so it fits too
+                let acc = (start + rest.total) as i64;
+                let repair = Repair {
+                    pc,
+                    original,
+                    patched,
+                    acc,
+                };
+                if halt != Halt::Overflow || terminates(program, &repair) {
+                    repairs.push(repair);
+                }
+            }
+        }
+        if machine.step().is_some() {
+            return repairs;
+        }
+    }
+}
+
+/// Whether the program terminates with the repair applied
+fn terminates(program: &Program, repair: &Repair) -> bool {
+    let mut repaired = program.clone();
+    repaired.instructions[repair.pc] = repair.patched;
+    Machine::new(&repaired).run() == Halt::Terminated
+}
+
+/// Finds the single `jmp` or `nop` to flip so the program terminates, in linear time
+///
+/// Returns the first of `all_repairs`, or `None` if the program already terminates or no
+/// single flip fixes it.
+pub fn repair(program: &Program) -> Option<Repair> {
+    all_repairs(program).into_iter().next()
+}
+
+/// The fewest `jmp` or `nop` to flip so the program terminates
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MinimalRepair {
+    /// Each changed instruction, in the order the repaired program runs them
+    pub repairs: Vec<Repair>,
+    /// Value of the accumulator when the repaired program terminates
+    pub acc: i64,
+}
+
+/// Finds the fewest flips so the program terminates, with a 0-1 breadth first search
+///
+/// Each instruction leads to its usual next one for free, or to the one its flipped
+/// version leads to for one flip. The cheapest path to the end never runs an instruction
+/// twice, so flipping the instructions along it fixes the program. There is always a
+/// path, as turning every `jmp` into a `nop` runs the whole program in order, unless the
+/// program uses registered opcodes, which the search does not follow. Returns `None` when
+/// there is no path, or when the accumulator overflows running the repaired program, and
+/// no repairs if the original program already terminates.
+///
+/// The search does not look at the accumulator, so the path it finds may overflow while
+/// another one with as many flips does not. A single flip is therefore looked for first
+/// with `repair`, which does, so both agree on which programs one flip fixes.
+pub fn minimal_repair(program: &Program) -> Option<MinimalRepair> {
+    let mut machine = Machine::new(program);
+    if machine.run() == Halt::Terminated {
+        return Some(MinimalRepair {
+            repairs: Vec::new(),
+            acc: machine.acc,
+        });
+    }
+    if let Some(single) = repair(program) {
+        return Some(MinimalRepair {
+            repairs: vec![single],
+            acc: single.acc,
+        });
+    }
+
+    let len = program.len();
+    let mut flips = vec![usize::MAX; len + 1];
+    // How each position was reached: the previous one and whether it was flipped
+    let mut previous: Vec<Option<(usize, bool)>> = vec![None; len + 1];
+    let mut pending = VecDeque::new();
+    flips[0] = 0;
+    pending.push_back(0);
+    while let Some(pc) = pending.pop_front() {
+        if pc == len {
+            break;
+        }
+        let instruction = program.instructions[pc];
+        let options = [(Some(instruction), false), (instruction.flipped(), true)];
+        for (next_instruction, flipped) in options.iter() {
+            let next = next_instruction.and_then(|i| target(program, pc, i));
+            let cost = flips[pc] + *flipped as usize;
+            if let Some(next) = next.filter(|next| cost < flips[*next]) {
+                flips[next] = cost;
+                previous[next] = Some((pc, *flipped));
+                if *flipped {
+                    pending.push_back(next);
+                } else {
+                    pending.push_front(next);
+                }
+            }
+        }
+    }
+
+    let mut repairs = Vec::new();
+    let mut repaired = program.clone();
+    let mut pc = len;
+    while let Some((from, flipped)) = previous[pc] {
+        if flipped {
+            let original = program.instructions[from];
+            let patched = original.flipped().unwrap();
+            repaired.instructions[from] = patched;
+            repairs.push(Repair {
+                pc: from,
+                original,
+                patched,
+                acc: 0,
+            });
+        }
+        pc = from;
+    }
+    if pc != 0 {
+        return None;
+    }
+    repairs.reverse();
+
+    let mut machine = Machine::new(&repaired);
+    if machine.run() != Halt::Terminated {
+        return None;
+    }
+    for repair in repairs.iter_mut() {
+        repair.acc = machine.acc;
+    }
+    Some(MinimalRepair {
+        repairs,
+        acc: machine.acc,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::program::CODE;
+
+    /// How much each instruction adds to the accumulator until the program terminates
+    ///
+    /// Those that never lead to the end, or whose total does not fit, are `None`.
+    fn acc_to_end(program: &Program) -> Vec<Option<i64>> {
+        let mut to_end: Vec<Option<i64>> = sums_to_end(program)
+            .iter()
+            .map(|sums| sums.and_then(|sums| i64::try_from(sums.total).ok()))
+            .collect();
+        to_end.truncate(program.len());
+        to_end
+    }
+
+    #[test]
+    fn test_acc_to_end() {
+        let program: Program = CODE.parse().unwrap();
+        let mut expected = vec![None; 9];
+        expected[8] = Some(6);
+        assert_eq!(acc_to_end(&program), expected);
+
+        let program: Program = "acc +1\nnop +0\nacc +2\njmp -3".parse().unwrap();
+        assert_eq!(acc_to_end(&program), vec![None, None, None, None]);
+        let program: Program = "acc +1\njmp +2\nacc +5\nacc +2".parse().unwrap();
+        assert_eq!(
+            acc_to_end(&program),
+            vec![Some(3), Some(2), Some(7), Some(2)]
+        );
+    }
+
+    #[test]
+    fn test_repair() {
+        let program: Program = CODE.parse().unwrap();
+        assert_eq!(
+            repair(&program),
+            Some(Repair {
+                pc: 7,
+                original: Instruction::Jmp(-4),
+                patched: Instruction::Nop(-4),
+                acc: 8
+            })
+        );
+    }
+
+    #[test]
+    fn test_repair_jump_to_end() {
+        let program: Program = "acc +1\nnop +2\njmp -2".parse().unwrap();
+        let repaired = repair(&program).unwrap();
+        assert_eq!((repaired.pc, repaired.acc), (1, 1));
+    }
+
+    #[test]
+    fn test_all_repairs() {
+        // Both the nop at 1 and the jmp at 3 can be flipped
+        let program: Program = "acc +1\nnop +3\nacc +2\njmp -3\nacc +4".parse().unwrap();
+        let repairs: Vec<(usize, i64)> = all_repairs(&program)
+            .iter()
+            .map(|r| (r.pc, r.acc))
+            .collect();
+        assert_eq!(repairs, vec![(1, 5), (3, 7)]);
+        assert_eq!(repair(&program).map(|r| r.pc), Some(1));
+
+        let program: Program = "acc +1\nacc +2".parse().unwrap();
+        assert!(all_repairs(&program).is_empty());
+    }
+
+    #[test]
+    fn test_minimal_repair() {
+        let program: Program = "jmp +0\nacc +3\njmp -1\nacc +1".parse().unwrap();
+        assert_eq!(repair(&program), None);
+        let minimal = minimal_repair(&program).unwrap();
+        let pcs: Vec<usize> = minimal.repairs.iter().map(|r| r.pc).collect();
+        assert_eq!(pcs, vec![0, 2]);
+        assert_eq!(minimal.repairs[1].patched, Instruction::Nop(-1));
+        assert_eq!(minimal.acc, 4);
+
+        let program: Program = CODE.parse().unwrap();
+        let minimal = minimal_repair(&program).unwrap();
+        assert_eq!(minimal.repairs, vec![repair(&program).unwrap()]);
+
+        let program: Program = "acc +1\njmp +1".parse().unwrap();
+        let minimal = minimal_repair(&program).unwrap();
+        assert!(minimal.repairs.is_empty());
+        assert_eq!(minimal.acc, 1);
+    }
+
+    #[test]
+    fn test_repair_dynamic() {
+        let set = crate::opcodes::InstructionSet::extended();
+        let program = set.parse_program("mul +2\nacc +1\njmp -1\nacc +5").unwrap();
+        let repaired = repair(&program).unwrap();
+        assert_eq!((repaired.pc, repaired.acc), (2, 6));
+
+        let program = set.parse_program("jz +0\nacc +1").unwrap();
+        assert_eq!(repair(&program), None);
+        assert_eq!(minimal_repair(&program), None);
+    }
+
+    #[test]
+    fn test_repair_dynamic_loop() {
+        // The jnz loop runs the nop twice, but only the first time leads to a repair
+        let program = crate::opcodes::InstructionSet::extended()
+            .parse_program("acc +2\nnop +4\nacc -1\njnz -2\njmp +0")
+            .unwrap();
+        let repairs: Vec<(usize, i64)> = all_repairs(&program)
+            .iter()
+            .map(|r| (r.pc, r.acc))
+            .collect();
+        assert_eq!(repairs, vec![(1, 2), (4, 0)]);
+        let mut repaired = program.clone();
+        repaired.instructions[1] = Instruction::Jmp(4);
+        let mut machine = Machine::new(&repaired);
+        assert_eq!((machine.run(), machine.acc), (Halt::Terminated, 2));
+    }
+
+    #[test]
+    fn test_repair_halted() {
+        // It terminates through hlt, although no instruction leads to the end
+        let program = crate::opcodes::InstructionSet::extended()
+            .parse_program("nop +3\nhlt +0\nacc +1\nacc +2")
+            .unwrap();
+        assert_eq!(acc_to_end(&program)[0], None);
+        assert!(all_repairs(&program).is_empty());
+        assert_eq!(
+            minimal_repair(&program),
+            Some(MinimalRepair {
+                repairs: Vec::new(),
+                acc: 0
+            })
+        );
+    }
+
+    #[test]
+    fn test_repair_overflow() {
+        let program: Program = format!("nop +0\njmp +{}\nacc +1", i64::MAX)
+            .parse()
+            .unwrap();
+        assert_eq!(repair(&program).map(|r| (r.pc, r.acc)), Some((1, 1)));
+        assert_eq!(minimal_repair(&program).unwrap().repairs.len(), 1);
+
+        let program: Program = format!("acc +{0}\nacc +{0}\nacc -5", i64::MAX)
+            .parse()
+            .unwrap();
+        assert_eq!(
+            acc_to_end(&program),
+            vec![None, Some(i64::MAX - 5), Some(-5)]
+        );
+        assert_eq!(repair(&program), None);
+        assert_eq!(minimal_repair(&program), None);
+    }
+
+    #[test]
+    fn test_repair_overflow_on_the_way() {
+        // The end is reached with i64::MAX, but only after going past it
+        let program: Program = format!("acc +{}\njmp +0\nacc +1\nacc -1", i64::MAX)
+            .parse()
+            .unwrap();
+        let mut repaired = program.clone();
+        repaired.instructions[1] = Instruction::Nop(0);
+        assert_eq!(Machine::new(&repaired).run(), Halt::Overflow);
+        assert_eq!(repair(&program), None);
+        assert_eq!(minimal_repair(&program), None);
+
+        // Going down first and then up stays within range
+        let program: Program = format!("acc +{}\njmp +0\nacc -1\nacc +1", i64::MAX)
+            .parse()
+            .unwrap();
+        assert_eq!(repair(&program).map(|r| (r.pc, r.acc)), Some((1, i64::MAX)));
+    }
+
+    #[test]
+    fn test_repair_through_large_values() {
+        // The rest of the program adds 0, though adding its end alone would overflow
+        let program: Program = format!(
+            "nop -1\njmp -2\nacc {}\nacc +{}\nacc +1",
+            i64::MIN,
+            i64::MAX
+        )
+        .parse()
+        .unwrap();
+        assert_eq!(repair(&program).map(|r| (r.pc, r.acc)), Some((1, 0)));
+    }
+
+    #[test]
+    fn test_repair_after_overflow() {
+        // Flipping the nop leads back to it, although the line before it reaches the end
+        let program: Program = format!("acc +11\nacc -10\nnop -1\nacc +{}", i64::MAX)
+            .parse()
+            .unwrap();
+        assert_eq!(Machine::new(&program).run(), Halt::Overflow);
+        assert!(all_repairs(&program).is_empty());
+        assert_eq!(repair(&program), None);
+
+        let program: Program = format!("nop +2\nacc +{}\nacc +1", i64::MAX)
+            .parse()
+            .unwrap();
+        assert_eq!(Machine::new(&program).run(), Halt::Overflow);
+        assert_eq!(repair(&program).map(|r| (r.pc, r.acc)), Some((0, 1)));
+    }
+
+    #[test]
+    fn test_repairs_agree() {
+        let mut rng = aoc_common::Rng::new(3);
+        let values = [-2, -1, 1, 2, i64::MAX, i64::MIN];
+        for _ in 0..2000 {
+            let len = 1 + rng.below(6);
+            let instructions: Vec<Instruction> = (0..len)
+                .map(|_| match rng.below(3) {
+                    0 => Instruction::Acc(values[rng.below(values.len())]),
+                    1 => Instruction::Jmp(rng.below(7) as i64 - 3),
+                    _ => Instruction::Nop(rng.below(7) as i64 - 3),
+                })
+                .collect();
+            let program = Program { instructions };
+
+            let repairs = all_repairs(&program);
+            for repaired in repairs.iter() {
+                let mut patched = program.clone();
+                patched.instructions[repaired.pc] = repaired.patched;
+                let mut machine = Machine::new(&patched);
+                assert_eq!(machine.run(), Halt::Terminated, "{:?}", program);
+                assert_eq!(machine.acc, repaired.acc);
+            }
+            let cost = minimal_repair(&program).map(|m| m.repairs.len());
+            assert_eq!(cost == Some(1), !repairs.is_empty(), "{:?}", program);
+        }
+    }
+
+    #[test]
+    fn test_cannot_repair() {
+        let program: Program = "acc +1\nacc +2".parse().unwrap();
+        assert_eq!(repair(&program), None);
+        let program: Program = "jmp +0\njmp -1".parse().unwrap();
+        assert_eq!(repair(&program), None);
+        assert_eq!(repair(&"".parse().unwrap()), None);
+    }
+}